@@ -1,10 +1,50 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::slot_hashes;
 use anchor_lang::system_program::{transfer, Transfer};
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{mint_to, Mint, MintTo, TokenAccount, TokenInterface}
+    token_2022::{
+        spl_token_2022::{
+            extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+            instruction::AuthorityType,
+            state::Mint as MintState,
+        },
+        Token2022,
+    },
+    token_interface::{
+        approve, burn, close_account, freeze_account, mint_to, set_authority, transfer_checked,
+        token_group_initialize, token_member_initialize, token_metadata_initialize,
+        spl_pod::optional_keys::OptionalNonZeroPubkey,
+        spl_token_metadata_interface::state::TokenMetadata,
+        Approve, Burn, CloseAccount, FreezeAccount, Mint, MintTo, SetAuthority, TokenAccount,
+        TokenGroupInitialize, TokenInterface, TokenMemberInitialize, TokenMetadataInitialize,
+        TransferChecked,
+    },
 };
+use solana_sha256_hasher::hashv;
 use switchboard_on_demand::accounts::RandomnessAccountData;
+use switchboard_on_demand::{ON_DEMAND_DEVNET_PID, ON_DEMAND_MAINNET_PID};
+use anchor_lang::solana_program::{instruction::Instruction, program::invoke};
+use mpl_core::{
+    accounts::BaseAssetV1,
+    instructions::{
+        AddPluginV1Cpi, AddPluginV1CpiAccounts, AddPluginV1InstructionArgs,
+        BurnV1Cpi, BurnV1CpiAccounts, BurnV1InstructionArgs,
+        CreateCollectionV2Cpi, CreateCollectionV2CpiAccounts, CreateCollectionV2InstructionArgs,
+        CreateV2Cpi, CreateV2CpiAccounts, CreateV2InstructionArgs,
+    },
+    types::{Attribute, Attributes, DataState, FreezeDelegate, Plugin, PluginAuthority, PluginAuthorityPair},
+};
+use mpl_bubblegum::{
+    hash::{hash_creators, hash_metadata},
+    instructions::{
+        BurnCpi, BurnCpiAccounts, BurnInstructionArgs,
+        CreateTreeConfigCpi, CreateTreeConfigCpiAccounts, CreateTreeConfigInstructionArgs,
+        MintToCollectionV1Cpi, MintToCollectionV1CpiAccounts, MintToCollectionV1InstructionArgs,
+    },
+    types::{LeafSchema, MetadataArgs, TokenProgramVersion, TokenStandard},
+    utils::get_asset_id,
+};
 use anchor_spl::metadata::{
     Metadata,
     MetadataAccount,
@@ -12,10 +52,17 @@ use anchor_spl::metadata::{
     CreateMasterEditionV3,
     SignMetadata,
     SetAndVerifySizedCollectionItem,
+    FreezeDelegatedAccount,
+    BurnNft,
+    UpdateMetadataAccountsV2,
+    mpl_token_metadata::{MAX_NAME_LENGTH, MAX_SYMBOL_LENGTH, MAX_URI_LENGTH},
     create_master_edition_v3,
     create_metadata_accounts_v3,
     sign_metadata,
     set_and_verify_sized_collection_item,
+    freeze_delegated_account,
+    burn_nft,
+    update_metadata_accounts_v2,
     mpl_token_metadata::types::{
             CollectionDetails,
             Creator, 
@@ -31,46 +78,225 @@ pub const NAME: &str = "Token Lottery Ticket #";
 pub const URI: &str = "Token Lottery";
 #[constant]
 pub const SYMBOL: &str = "TICKET";
+#[constant]
+pub const TICKET_ID_PLACEHOLDER: &str = "{id}";
+/// Digits reserved for the ticket index when a lottery has no `max_tickets`, enough for
+/// ten billion tickets.
+#[constant]
+pub const UNCAPPED_TICKET_INDEX_DIGITS: usize = 10;
+#[constant]
+pub const WINNER_SUFFIX: &str = " WINNER";
+#[constant]
+pub const TICKET_MINT_SEED: &[u8] = b"ticket_mint";
+#[constant]
+pub const TICKET_RECORD_SEED: &[u8] = b"ticket";
+#[constant]
+pub const TICKET_RECEIPT_SEED: &[u8] = b"receipt";
+#[constant]
+pub const BUYER_STATE_SEED: &[u8] = b"buyer";
+#[constant]
+pub const CORE_COLLECTION_SEED: &[u8] = b"core_collection";
+#[constant]
+pub const MAX_FEE_BPS: u16 = 10_000;
+#[constant]
+pub const MAX_PRIZE_TIERS: u8 = 10;
+#[constant]
+pub const TOTAL_PRIZE_BPS: u16 = 10_000;
+#[constant]
+pub const DEFAULT_SEED_SLOT_WINDOW: u64 = 10;
+#[constant]
+pub const MAX_RANDOMNESS_HISTORY: u8 = 8;
+/// Slots the SlotHashes sysvar keeps. A commit-reveal secret must be revealed within this many
+/// slots of `reveal_slot`; after that anyone can draw without it.
+#[constant]
+pub const COMMIT_REVEAL_DEADLINE_SLOTS: u64 = 512;
+
+pub const SPL_ACCOUNT_COMPRESSION_ID: Pubkey = pubkey!("cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK");
+pub const SPL_NOOP_ID: Pubkey = pubkey!("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV");
+/// Anchor discriminator of the account compression program's `verify_leaf` instruction.
+const VERIFY_LEAF_DISCRIMINATOR: [u8; 8] = [124, 220, 22, 223, 104, 10, 250, 224];
+
+/// Returns the canonical ticket mint address for `ticket_id` in `token_lottery`.
+#[cfg(feature = "cpi")]
+pub fn ticket_mint_address(token_lottery: &Pubkey, ticket_id: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[TICKET_MINT_SEED, token_lottery.as_ref(), ticket_id.to_le_bytes().as_ref()],
+        &ID,
+    ).0
+}
 
 #[program]
 pub mod token_lottery {
 
     use super::*;
 
-    pub fn initialize_config(ctx: Context<InitializeConfig>, start: u64, end: u64, price: u64) -> Result<()> {
+    pub fn initialize_config(ctx: Context<InitializeConfig>, lottery_id: u64, params: InitializeConfigParams) -> Result<()> {
+        require!(params.fee_bps <= MAX_FEE_BPS, ErrorCode::InvalidFee);
+
+        // 賞金テーブルを指定しない場合は1等のみ（100%）
+        let prize_tiers = params.prize_tiers.unwrap_or_else(|| vec![TOTAL_PRIZE_BPS]);
+        validate_prize_tiers(&prize_tiers)?;
+
         ctx.accounts.token_lottery.bump = ctx.bumps.token_lottery;
-        ctx.accounts.token_lottery.lottery_start = start;
-        ctx.accounts.token_lottery.lottery_end = end;
-        ctx.accounts.token_lottery.price = price;
+        ctx.accounts.token_lottery.lottery_id = lottery_id;
+        ctx.accounts.token_lottery.creator = ctx.accounts.payer.key();
+        ctx.accounts.token_lottery.lottery_start = params.start;
+        ctx.accounts.token_lottery.lottery_end = params.end;
+        ctx.accounts.token_lottery.schedule_mode = params.schedule_mode;
+        ctx.accounts.token_lottery.validate_schedule(&Clock::get()?)?;
+
+        // メタデータを指定しない場合はデフォルトの名前・シンボル・URIを使う
+        let metadata = params.metadata.unwrap_or_default();
+        metadata.validate(params.max_tickets)?;
+        ctx.accounts.token_lottery.metadata = metadata;
+        ctx.accounts.token_lottery.price = params.price;
+        ctx.accounts.token_lottery.fee_bps = params.fee_bps;
+        ctx.accounts.token_lottery.treasury = params.treasury;
+        ctx.accounts.token_lottery.prize_tiers = prize_tiers;
+        ctx.accounts.token_lottery.cancel_timeout = params.cancel_timeout;
+        ctx.accounts.token_lottery.winning_ticket_action = params.winning_ticket_action;
+        ctx.accounts.token_lottery.ticket_mode = params.ticket_mode;
+        ctx.accounts.token_lottery.validate_ticket_mode()?;
+        ctx.accounts.token_lottery.max_tickets = params.max_tickets;
+        ctx.accounts.token_lottery.max_tickets_per_wallet = params.max_tickets_per_wallet;
+        ctx.accounts.token_lottery.randomness_provider = params.randomness_provider;
+        ctx.accounts.token_lottery.permissionless_draw = params.permissionless_draw;
+        ctx.accounts.token_lottery.crank_reward = params.crank_reward;
+        ctx.accounts.token_lottery.recommit_timeout = params.recommit_timeout;
+
+        match params.randomness_provider {
+            RandomnessProvider::Switchboard { seed_slot_window } => {
+                require!(seed_slot_window > 0, ErrorCode::InvalidSeedSlotWindow);
+            }
+            // コミット・リビール方式ではauthorityが秘密値のハッシュを先にコミットする
+            RandomnessProvider::CommitReveal => {
+                ctx.accounts.token_lottery.secret_commitment = params.secret_commitment
+                    .ok_or(ErrorCode::MissingSecretCommitment)?;
+            }
+            RandomnessProvider::Orao => {}
+        }
         ctx.accounts.token_lottery.authority = ctx.accounts.payer.key();
         ctx.accounts.token_lottery.randomness_account = Pubkey::default();
 
+        // payment_mint を指定しない場合はSOL建て
+        ctx.accounts.token_lottery.payment_mint = match &ctx.accounts.payment_mint {
+            Some(payment_mint) => {
+                require!(ctx.accounts.vault.is_some(), ErrorCode::MissingPaymentAccounts);
+                validate_payment_mint(&payment_mint.to_account_info())?;
+                payment_mint.key()
+            }
+            None => Pubkey::default(),
+        };
+
         ctx.accounts.token_lottery.total_tickets = 0;
         ctx.accounts.token_lottery.lottery_pot_amount = 0;
+        ctx.accounts.token_lottery.accrued_fees = 0;
         ctx.accounts.token_lottery.winner_chosen = false;
+        ctx.accounts.token_lottery.cancelled = false;
+        ctx.accounts.token_lottery.prize_claimed = false;
+
+        emit!(LotteryCreated {
+            lottery: ctx.accounts.token_lottery.key(),
+            lottery_id,
+            authority: ctx.accounts.token_lottery.authority,
+            payment_mint: ctx.accounts.token_lottery.payment_mint,
+            price: params.price,
+            start: params.start,
+            end: params.end,
+            schedule_mode: params.schedule_mode,
+            slot: Clock::get()?.slot,
+        });
+
         Ok(())
     }
 
     pub fn initialize_lottery(ctx: Context<InitializeLottery>) -> Result<()> {
-        
+        // Token-2022のコレクションは initialize_token_group で作成する
+        require!(
+            ctx.accounts.token_lottery.ticket_mode != TicketMode::Token2022,
+            ErrorCode::InvalidTicketMode
+        );
+        let token_lottery_key = ctx.accounts.token_lottery.key();
+
+        // Coreチケットの抽選ではCoreコレクションだけを作成する（更新権限は抽選PDA）
+        if ctx.accounts.token_lottery.ticket_mode == TicketMode::Core {
+            let (Some(core_collection), Some(mpl_core_program)) =
+                (&ctx.accounts.core_collection, &ctx.accounts.mpl_core_program)
+            else {
+                return err!(ErrorCode::MissingCoreAccounts);
+            };
+            let (_, core_collection_bump) = Pubkey::find_program_address(
+                &[CORE_COLLECTION_SEED, token_lottery_key.as_ref()],
+                &ID,
+            );
+            let signer_seeds: &[&[&[u8]]] = &[&[
+                CORE_COLLECTION_SEED,
+                token_lottery_key.as_ref(),
+                &[core_collection_bump],
+            ]];
+
+            let metadata = &ctx.accounts.token_lottery.metadata;
+            let token_lottery = ctx.accounts.token_lottery.to_account_info();
+            CreateCollectionV2Cpi::new(
+                mpl_core_program,
+                CreateCollectionV2CpiAccounts {
+                    collection: core_collection,
+                    update_authority: Some(&token_lottery),
+                    payer: &ctx.accounts.payer,
+                    system_program: &ctx.accounts.system_program,
+                },
+                CreateCollectionV2InstructionArgs {
+                    name: metadata.name_prefix.clone(),
+                    uri: metadata.collection_uri.clone(),
+                    plugins: None,
+                    external_plugin_adapters: None,
+                },
+            ).invoke_signed(signer_seeds)?;
+
+            emit!(LotteryInitialized {
+                lottery: token_lottery_key,
+                collection_mint: core_collection.key(),
+                slot: Clock::get()?.slot,
+            });
+
+            return Ok(());
+        }
+
+        let (
+            Some(collection_mint),
+            Some(collection_token_account),
+            Some(collection_metadata),
+            Some(master_edition),
+            Some(collection_mint_bump),
+        ) = (
+            &ctx.accounts.collection_mint,
+            &ctx.accounts.collection_token_account,
+            &ctx.accounts.metadata,
+            &ctx.accounts.master_edition,
+            ctx.bumps.collection_mint,
+        ) else {
+            return err!(ErrorCode::MissingCollectionAccounts);
+        };
+
         let signer_seeds: &[&[&[u8]]] = &[&[
             b"collection_mint".as_ref(),
-            &[ctx.bumps.collection_mint],
+            token_lottery_key.as_ref(),
+            &[collection_mint_bump],
         ]];
 
         // 1. コレクションNFTをミント
         msg!("Creating mint accounts");
 
         let mint_to_accounts = MintTo {
-            mint: ctx.accounts.collection_mint.to_account_info(),
-            to: ctx.accounts.collection_token_account.to_account_info(),
-            authority: ctx.accounts.collection_mint.to_account_info(),
+            mint: collection_mint.to_account_info(),
+            to: collection_token_account.to_account_info(),
+            authority: collection_mint.to_account_info(),
         };
 
         let mint_to_cpi_context = CpiContext::new(
             ctx.accounts.token_program.to_account_info(),
             mint_to_accounts,
-        ).with_signer(&signer_seeds);
+        ).with_signer(signer_seeds);
 
         mint_to(
             mint_to_cpi_context,
@@ -80,22 +306,23 @@ pub mod token_lottery {
         // 2. メタデータアカウントを作成
         msg!("Creating metadata accounts");
         let create_metadata_accounts_v3_accounts = CreateMetadataAccountsV3 {
-            metadata: ctx.accounts.metadata.to_account_info(),
-            mint: ctx.accounts.collection_mint.to_account_info(),
-            mint_authority: ctx.accounts.collection_mint.to_account_info(),
-            update_authority: ctx.accounts.collection_mint.to_account_info(),
+            metadata: collection_metadata.to_account_info(),
+            mint: collection_mint.to_account_info(),
+            mint_authority: collection_mint.to_account_info(),
+            update_authority: collection_mint.to_account_info(),
             payer: ctx.accounts.payer.to_account_info(),
             system_program: ctx.accounts.system_program.to_account_info(),
             rent: ctx.accounts.rent.to_account_info(),
         };
 
+        let metadata = &ctx.accounts.token_lottery.metadata;
         let data_v2 = DataV2 {
-            name: NAME.to_string(),             // NFTの名前
-            symbol: SYMBOL.to_string(),         // NFTのシンボル
-            uri: URI.to_string(),               // メタデータJSONのURI
+            name: metadata.name_prefix.clone(), // NFTの名前
+            symbol: metadata.symbol.clone(),    // NFTのシンボル
+            uri: metadata.collection_uri.clone(), // メタデータJSONのURI
             seller_fee_basis_points: 0,         // ロイヤリティ（0 = 0%、10000 = 100%）
             creators: Some(vec![Creator {
-                address: ctx.accounts.collection_mint.key(),
+                address: collection_mint.key(),
                 verified: false,                // 署名で検証済みかどうか
                 share: 100,                     // ロイヤリティの分配割合（合計100）
             }]),
@@ -106,7 +333,7 @@ pub mod token_lottery {
         let create_metadata_cpi_context = CpiContext::new(
             ctx.accounts.token_metadata_program.to_account_info(),
             create_metadata_accounts_v3_accounts,
-        ).with_signer(&signer_seeds);
+        ).with_signer(signer_seeds);
 
         create_metadata_accounts_v3(
             create_metadata_cpi_context,
@@ -121,11 +348,11 @@ pub mod token_lottery {
         msg!("Creating Master edition accounts");
         let create_master_edition_v3_accounts = CreateMasterEditionV3 {
             payer: ctx.accounts.payer.to_account_info(),
-            mint: ctx.accounts.collection_mint.to_account_info(),
-            edition: ctx.accounts.master_edition.to_account_info(),
-            mint_authority: ctx.accounts.collection_mint.to_account_info(),
-            update_authority: ctx.accounts.collection_mint.to_account_info(),
-            metadata: ctx.accounts.metadata.to_account_info(),
+            mint: collection_mint.to_account_info(),
+            edition: master_edition.to_account_info(),
+            mint_authority: collection_mint.to_account_info(),
+            update_authority: collection_mint.to_account_info(),
+            metadata: collection_metadata.to_account_info(),
             token_program: ctx.accounts.token_program.to_account_info(),
             system_program: ctx.accounts.system_program.to_account_info(),
             rent: ctx.accounts.rent.to_account_info(),
@@ -134,7 +361,7 @@ pub mod token_lottery {
         let create_master_edition_cpi_context = CpiContext::new(
             ctx.accounts.token_metadata_program.to_account_info(),
             create_master_edition_v3_accounts,
-        ).with_signer(&signer_seeds);
+        ).with_signer(signer_seeds);
 
         create_master_edition_v3(
             create_master_edition_cpi_context,
@@ -145,50 +372,63 @@ pub mod token_lottery {
         msg!("Verifying collection");
         
         let sign_metadata_accounts = SignMetadata {
-            creator: ctx.accounts.collection_mint.to_account_info(),
-            metadata: ctx.accounts.metadata.to_account_info(),
+            creator: collection_mint.to_account_info(),
+            metadata: collection_metadata.to_account_info(),
         };
 
         let sign_metadata_cpi_context = CpiContext::new(
             ctx.accounts.token_metadata_program.to_account_info(),
             sign_metadata_accounts,
-        ).with_signer(&signer_seeds);
+        ).with_signer(signer_seeds);
 
         sign_metadata(sign_metadata_cpi_context)?;
 
+        emit!(LotteryInitialized {
+            lottery: token_lottery_key,
+            collection_mint: collection_mint.key(),
+            slot: Clock::get()?.slot,
+        });
+
         Ok(())
     }
 
-    pub fn buy_ticket(ctx: Context<BuyTicket>) -> Result<()> {
+    pub fn buy_ticket(ctx: Context<BuyTicket>, entropy: Option<[u8; 32]>) -> Result<()> {
         let clock = Clock::get()?;
-        let ticket_name = NAME.to_owned() + ctx.accounts.token_lottery.total_tickets.to_string().as_str();
+        let ticket_id = ctx.accounts.token_lottery.total_tickets;
+        let ticket_name = ctx.accounts.token_lottery.metadata.ticket_name(ticket_id)?;
+        let ticket_uri = ctx.accounts.token_lottery.metadata.ticket_uri(ticket_id)?;
+        let ticket_symbol = ctx.accounts.token_lottery.metadata.symbol.clone();
 
         require!(
-            clock.slot >= ctx.accounts.token_lottery.lottery_start &&
-            clock.slot <= ctx.accounts.token_lottery.lottery_end,
-            ErrorCode::LotteryNotOpen
+            ctx.accounts.token_lottery.ticket_mode == TicketMode::TokenMetadata,
+            ErrorCode::InvalidTicketMode
         );
+        ctx.accounts.token_lottery.check_sales_open(&clock)?;
+        ctx.accounts.token_lottery.check_ticket_limits(ctx.accounts.buyer_state.tickets_bought, 1)?;
 
         // 1. チケット代金を支払う
-        let transfer_accounts = Transfer {
-            from: ctx.accounts.payer.to_account_info(),
-            to: ctx.accounts.token_lottery.to_account_info(),
-        };
-
-        let transfer_cpi_context = CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
-            transfer_accounts,
-        );
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
 
-        transfer(
-            transfer_cpi_context,
-            ctx.accounts.token_lottery.price,  // amount: 支払うSOLの量
+        deposit_to_pot(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            payment,
+            ctx.accounts.token_lottery.price,  // amount: 支払う金額（SOLまたはトークン）
         )?;
 
-        ctx.accounts.token_lottery.lottery_pot_amount += ctx.accounts.token_lottery.price;
+        ctx.accounts.token_lottery.record_sales(1)?;
 
+        let token_lottery_key = ctx.accounts.token_lottery.key();
         let signer_seeds: &[&[&[u8]]] = &[&[
             b"collection_mint".as_ref(),
+            token_lottery_key.as_ref(),
             &[ctx.bumps.collection_mint],
         ]];
 
@@ -202,7 +442,7 @@ pub mod token_lottery {
         let mint_to_cpi_context = CpiContext::new(
             ctx.accounts.token_program.to_account_info(),
             mint_to_accounts,
-        ).with_signer(&signer_seeds);
+        ).with_signer(signer_seeds);
 
         mint_to(
             mint_to_cpi_context,
@@ -222,8 +462,8 @@ pub mod token_lottery {
 
         let data_v2 = DataV2 {
             name: ticket_name,                  // チケット名（例: "Token Lottery Ticket #0"）
-            symbol: SYMBOL.to_string(),         // NFTのシンボル
-            uri: URI.to_string(),               // メタデータJSONのURI
+            symbol: ticket_symbol,              // NFTのシンボル
+            uri: ticket_uri,                    // メタデータJSONのURI（{id} をチケット番号に置換）
             seller_fee_basis_points: 0,         // ロイヤリティ（0%）
             creators: None,                     // クリエイター情報（なし）
             collection: None,                   // コレクションは後でset_and_verify_sized_collection_itemで設定
@@ -233,7 +473,7 @@ pub mod token_lottery {
         let create_metadata_cpi_context = CpiContext::new(
             ctx.accounts.token_metadata_program.to_account_info(),
             create_metadata_accounts_v3_accounts,
-        ).with_signer(&signer_seeds);
+        ).with_signer(signer_seeds);
 
         create_metadata_accounts_v3(
             create_metadata_cpi_context,
@@ -259,7 +499,7 @@ pub mod token_lottery {
         let create_master_edition_cpi_context = CpiContext::new(
             ctx.accounts.token_metadata_program.to_account_info(),
             create_master_edition_v3_accounts,
-        ).with_signer(&signer_seeds);
+        ).with_signer(signer_seeds);
 
         create_master_edition_v3(
             create_master_edition_cpi_context,
@@ -280,14 +520,43 @@ pub mod token_lottery {
         let set_and_verify_cpi_context = CpiContext::new(
             ctx.accounts.token_metadata_program.to_account_info(),
             set_and_verify_sized_collection_item_accounts,
-        ).with_signer(&signer_seeds);
+        ).with_signer(signer_seeds);
 
         set_and_verify_sized_collection_item(
             set_and_verify_cpi_context,
             None,  // collection_authority_record: コレクション権限の委譲レコード（なし）
         )?;
 
+        // 6. チケットの記録を保存
+        ctx.accounts.ticket_record.set_inner(TicketRecord {
+            bump: ctx.bumps.ticket_record,
+            lottery: ctx.accounts.token_lottery.key(),
+            ticket_index: ctx.accounts.token_lottery.total_tickets,
+            mint: ctx.accounts.ticket_mint.key(),
+            purchase_slot: clock.slot,
+        });
+
+        ctx.accounts.buyer_state.record_purchase(
+            ctx.bumps.buyer_state,
+            ctx.accounts.token_lottery.key(),
+            ctx.accounts.payer.key(),
+            1,
+        );
+        if let Some(entropy) = entropy {
+            ctx.accounts.token_lottery.mix_entropy(&ctx.accounts.payer.key(), &entropy);
+        }
+
+        emit!(TicketPurchased {
+            lottery: ctx.accounts.token_lottery.key(),
+            buyer: ctx.accounts.payer.key(),
+            first_ticket_id: ctx.accounts.token_lottery.total_tickets,
+            count: 1,
+            amount: ctx.accounts.token_lottery.price,
+            slot: clock.slot,
+        });
+
         ctx.accounts.token_lottery.total_tickets += 1;
+        ctx.accounts.token_lottery.record_ticket_opened()?;
 
         Ok(())
     }
@@ -295,355 +564,5025 @@ pub mod token_lottery {
     pub fn commit_winner(ctx: Context<CommitWinner>) -> Result<()> {
         let clock = Clock::get()?;
         let token_lottery = &mut ctx.accounts.token_lottery;
+        token_lottery.check_can_draw(&ctx.accounts.payer.key())?;
+        // 販売終了後でなければ乱数をコミットできない
+        require!(token_lottery.sales_ended(&clock), ErrorCode::LotteryNotCompleted);
+        require!(!token_lottery.cancelled, ErrorCode::LotteryCancelled);
+        // 販売前にコミットすると結果を知ってから販売期間を延ばせてしまう
+        require!(token_lottery.total_tickets > 0, ErrorCode::NoTicketsSold);
+
+        // コミット済みの乱数は recommit_winner でしか差し替えられない
         require!(
-            ctx.accounts.payer.key() == token_lottery.authority,
-            ErrorCode::NotAuthorized
+            token_lottery.randomness_account == Pubkey::default(),
+            ErrorCode::RandomnessAlreadyCommitted
         );
 
-        let randomness_data = RandomnessAccountData::parse(ctx.accounts.randomness_account_data.data.borrow()).unwrap();
+        token_lottery.verify_commit(&ctx.accounts.randomness_account_data, &clock)?;
 
-        require!(
-            randomness_data.seed_slot == clock.slot - 1,
-            ErrorCode::RandomnessAlreadyRevealed
-        );
+        token_lottery.record_commit(ctx.accounts.randomness_account_data.key(), clock.slot);
 
-        token_lottery.randomness_account = ctx.accounts.randomness_account_data.key();
+        emit!(RandomnessCommitted {
+            lottery: token_lottery.key(),
+            randomness_account: token_lottery.randomness_account,
+            previous_randomness_account: None,
+            committer: ctx.accounts.payer.key(),
+            slot: clock.slot,
+        });
 
         Ok(())
     }
 
-    pub fn reveal_winner(ctx: Context<RevealWinner>) -> Result<()> {
+    pub fn recommit_winner(ctx: Context<RecommitWinner>) -> Result<()> {
         let clock = Clock::get()?;
         let token_lottery = &mut ctx.accounts.token_lottery;
+        token_lottery.check_can_draw(&ctx.accounts.payer.key())?;
 
-        require!(
-            ctx.accounts.randomness_account_data.key() == token_lottery.randomness_account,
-            ErrorCode::IncorrectRandomnessAccount
-        );
-        require!(
-            ctx.accounts.payer.key() == token_lottery.authority,
-            ErrorCode::NotAuthorized
-        );
-        require!(
-            clock.slot >= token_lottery.lottery_end,
-            ErrorCode::LotteryNotCompleted
-        );
+        // 結果が公開済みの乱数は引き直せない
+        let previous_revealed = token_lottery.randomness_provider.is_revealed(&ctx.accounts.previous_randomness_account)?;
+        let randomness_account = ctx.accounts.randomness_account_data.key();
+        token_lottery.check_can_recommit(&randomness_account, previous_revealed, clock.slot)?;
+
+        token_lottery.verify_commit(&ctx.accounts.randomness_account_data, &clock)?;
+
+        let previous = token_lottery.randomness_account;
+        token_lottery.randomness_history.push(previous);
+        token_lottery.record_commit(randomness_account, clock.slot);
+
+        emit!(RandomnessCommitted {
+            lottery: token_lottery.key(),
+            randomness_account,
+            previous_randomness_account: Some(previous),
+            committer: ctx.accounts.payer.key(),
+            slot: clock.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn reveal_winner(ctx: Context<RevealWinner>, secret: Option<[u8; 32]>) -> Result<()> {
+        let clock = Clock::get()?;
+        let token_lottery = &mut ctx.accounts.token_lottery;
+
+        // 期限切れ後のフォールバック抽選は秘密値を使わないので誰でも実行できる
+        let fallback = token_lottery.randomness_provider == RandomnessProvider::CommitReveal && secret.is_none();
+        if !fallback {
+            token_lottery.check_can_draw(&ctx.accounts.payer.key())?;
+        }
+        require!(token_lottery.sales_ended(&clock), ErrorCode::LotteryNotCompleted);
         require!(!token_lottery.winner_chosen, ErrorCode::WinnerChosen);
+        require!(!token_lottery.cancelled, ErrorCode::LotteryCancelled);
         require!(token_lottery.total_tickets > 0, ErrorCode::NoTicketsSold);
 
-        let randomness_data =
-            RandomnessAccountData::parse(ctx.accounts.randomness_account_data.data.borrow()).unwrap();
-        let revealed_random_value = randomness_data.get_value(clock.slot)
-            .map_err(|_| ErrorCode::RandomnessNotResolved)?;
+        let revealed_random_value = match token_lottery.randomness_provider {
+            RandomnessProvider::CommitReveal => {
+                // コミット時に決まったスロットのハッシュを使い、authorityにスロットを選ばせない
+                let hash_slot = token_lottery.commit_reveal_hash_slot(secret.is_some(), clock.slot)?;
+                let slot_hash = slot_hash_at(&ctx.accounts.recent_slothashes, hash_slot)?;
+                match secret {
+                    Some(secret) => token_lottery.reveal_secret(&secret, &slot_hash)?,
+                    None => token_lottery.fallback_randomness(&slot_hash),
+                }
+            }
+            randomness_provider => {
+                let randomness_account_data = ctx.accounts.randomness_account_data.as_ref()
+                    .ok_or(ErrorCode::IncorrectRandomnessAccount)?;
+                require!(
+                    randomness_account_data.key() == token_lottery.randomness_account,
+                    ErrorCode::IncorrectRandomnessAccount
+                );
+                randomness_provider.reveal(randomness_account_data, &clock)?
+            }
+        };
 
-        msg!("Randomness result: {}", revealed_random_value[0]);
+        msg!("Randomness result: {:?}", revealed_random_value);
         msg!("Ticket num: {}", token_lottery.total_tickets);
 
-        let randomness_result =
-            revealed_random_value[0] as u64 % token_lottery.total_tickets;
+        token_lottery.record_draw(&revealed_random_value);
+
+        msg!("Winners: {:?}", token_lottery.winning_ticket_ids);
 
-        msg!("Winner: {}", randomness_result);
+        // authority以外が抽選を完了させた場合は手数料から報酬を支払う
+        let mut reward = 0;
+        if ctx.accounts.payer.key() != token_lottery.authority {
+            reward = token_lottery.take_crank_reward();
+            if reward > 0 {
+                let payment = token_payment(
+                    &ctx.accounts.token_lottery,
+                    &ctx.accounts.payment_mint,
+                    &ctx.accounts.vault,
+                    &ctx.accounts.payer_token_account,
+                    &ctx.accounts.payment_token_program,
+                )?;
 
-        token_lottery.winning_ticket_id = randomness_result;
-        token_lottery.winner_chosen = true;
+                withdraw_from_pot(
+                    &ctx.accounts.token_lottery,
+                    &ctx.accounts.payer.to_account_info(),
+                    payment,
+                    reward,
+                )?;
+            }
+        }
+
+        emit!(WinnerRevealed {
+            lottery: ctx.accounts.token_lottery.key(),
+            winning_ticket_ids: ctx.accounts.token_lottery.winning_ticket_ids.clone(),
+            randomness: revealed_random_value,
+            revealer: ctx.accounts.payer.key(),
+            crank_reward: reward,
+            slot: clock.slot,
+        });
 
         Ok(())
     }
 
-    pub fn claim_prize(ctx: Context<ClaimPrize>) -> Result<()> {
+    pub fn claim_prize(ctx: Context<ClaimPrize>, ticket_id: u64) -> Result<()> {
         // Check if winner has been chosen
         msg!("Winner chosen: {}", ctx.accounts.token_lottery.winner_chosen);
-        require!(ctx.accounts.token_lottery.winner_chosen, ErrorCode::WinnerNotChosen);
-        
+        // Check if the ticket won a prize tier that has not been paid yet
+        let tier = ctx.accounts.token_lottery.claimable_tier(ticket_id)?;
+
         // Check if token is a part of the collection
         let collection = ctx.accounts.metadata.collection.as_ref()
             .ok_or(ErrorCode::NoCollection)?;
         require!(collection.verified, ErrorCode::NotVerifiedTicket);
         require!(collection.key == ctx.accounts.collection_mint.key(), ErrorCode::IncorrectTicket);
 
-        let ticket_name = NAME.to_owned() + &ctx.accounts.token_lottery.winning_ticket_id.to_string();
-        let metadata_name = ctx.accounts.metadata.name.replace("\u{0}", "");
+        // Check if the winner holds the ticket recorded at purchase
+        ctx.accounts.ticket_record.validate(&ctx.accounts.token_lottery.key(), ticket_id, &ctx.accounts.ticket_mint.key())?;
+        require!(ctx.accounts.destination.amount > 0, ErrorCode::IncorrectTicket);
 
-        msg!("Ticket name: {}", ticket_name);
-        msg!("Metadata name: {}", metadata_name);
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
 
-        // Check if the winner has the winning ticket
-        require!(metadata_name == ticket_name, ErrorCode::IncorrectTicket);
-        require!(ctx.accounts.destination.amount > 0, ErrorCode::IncorrectTicket);
+        pay_prize(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.payer.to_account_info(),
+            payment,
+            ticket_id,
+            tier,
+        )?;
+
+        // 当選チケットを設定どおりにバーンまたは凍結する
+        let token_lottery_key = ctx.accounts.token_lottery.key();
+        retire_winning_ticket(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.ticket_record,
+            ctx.accounts.payer.to_account_info(),
+            || {
+                // Metaplex経由でバーンし、メタデータ・エディション・ATAのレントを回収してコレクションサイズを減らす
+                let burn_nft_accounts = BurnNft {
+                    metadata: ctx.accounts.metadata.to_account_info(),
+                    owner: ctx.accounts.payer.to_account_info(),
+                    mint: ctx.accounts.ticket_mint.to_account_info(),
+                    token: ctx.accounts.destination.to_account_info(),
+                    edition: ctx.accounts.master_edition.to_account_info(),
+                    spl_token: ctx.accounts.token_program.to_account_info(),
+                };
+
+                let burn_nft_cpi_context = CpiContext::new(
+                    ctx.accounts.token_metadata_program.to_account_info(),
+                    burn_nft_accounts,
+                ).with_remaining_accounts(vec![ctx.accounts.collection_metadata.to_account_info()]);
+
+                burn_nft(burn_nft_cpi_context, Some(ctx.accounts.collection_metadata.key()))
+            },
+            || {
+                // 凍結権限はマスターエディションにあるので、collection_mintを委任先にしてMetaplex経由で凍結する
+                let approve_accounts = Approve {
+                    to: ctx.accounts.destination.to_account_info(),
+                    delegate: ctx.accounts.collection_mint.to_account_info(),
+                    authority: ctx.accounts.payer.to_account_info(),
+                };
+
+                let approve_cpi_context = CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    approve_accounts,
+                );
+
+                approve(approve_cpi_context, 1)?;
+
+                let signer_seeds: &[&[&[u8]]] = &[&[
+                    b"collection_mint".as_ref(),
+                    token_lottery_key.as_ref(),
+                    &[ctx.bumps.collection_mint],
+                ]];
+
+                let freeze_accounts = FreezeDelegatedAccount {
+                    metadata: ctx.accounts.metadata.to_account_info(),
+                    delegate: ctx.accounts.collection_mint.to_account_info(),
+                    token_account: ctx.accounts.destination.to_account_info(),
+                    edition: ctx.accounts.master_edition.to_account_info(),
+                    mint: ctx.accounts.ticket_mint.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                };
+
+                let freeze_cpi_context = CpiContext::new(
+                    ctx.accounts.token_metadata_program.to_account_info(),
+                    freeze_accounts,
+                ).with_signer(signer_seeds);
+
+                freeze_delegated_account(freeze_cpi_context)
+            },
+        )
+    }
+
+    pub fn mark_winner(ctx: Context<MarkWinner>, ticket_id: u64) -> Result<()> {
+        let token_lottery = &ctx.accounts.token_lottery;
+        require!(token_lottery.winner_chosen, ErrorCode::WinnerNotChosen);
+        let tier = token_lottery.winning_tier(ticket_id).ok_or(ErrorCode::IncorrectTicket)?;
+        ctx.accounts.ticket_record.validate(&token_lottery.key(), ticket_id, &ctx.accounts.ticket_mint.key())?;
 
+        // 名前に WINNER を付け、当選者用URIがあれば差し替える
+        // コレクション・クリエイターなどは現在のメタデータをそのまま引き継ぐ
+        let current = &ctx.accounts.metadata;
+        let data_v2 = DataV2 {
+            name: token_lottery.metadata.winner_name(ticket_id),
+            symbol: token_lottery.metadata.symbol.clone(),
+            uri: token_lottery.metadata.winner_uri(ticket_id)?,
+            seller_fee_basis_points: current.seller_fee_basis_points,
+            creators: current.creators.clone(),
+            collection: current.collection.clone(),
+            uses: current.uses.clone(),
+        };
+
+        let token_lottery_key = token_lottery.key();
         let signer_seeds: &[&[&[u8]]] = &[&[
-            b"token_lottery".as_ref(),
-            &[ctx.accounts.token_lottery.bump],
+            b"collection_mint".as_ref(),
+            token_lottery_key.as_ref(),
+            &[ctx.bumps.collection_mint],
         ]];
 
-        let transfer_accounts = Transfer {
-            from: ctx.accounts.token_lottery.to_account_info(),
-            to: ctx.accounts.payer.to_account_info(),
+        let update_metadata_accounts = UpdateMetadataAccountsV2 {
+            metadata: ctx.accounts.metadata.to_account_info(),
+            update_authority: ctx.accounts.collection_mint.to_account_info(),
         };
 
-        let transfer_cpi_context = CpiContext::new_with_signer(
-            ctx.accounts.system_program.to_account_info(),
-            transfer_accounts,
-            signer_seeds,
-        );
+        let update_metadata_cpi_context = CpiContext::new(
+            ctx.accounts.token_metadata_program.to_account_info(),
+            update_metadata_accounts,
+        ).with_signer(signer_seeds);
 
-        transfer(
-            transfer_cpi_context,
-            ctx.accounts.token_lottery.lottery_pot_amount,
+        update_metadata_accounts_v2(
+            update_metadata_cpi_context,
+            None,          // new_update_authority: 変更しない
+            Some(data_v2),
+            None,          // primary_sale_happened: 変更しない
+            None,          // is_mutable: 変更しない
         )?;
 
-        ctx.accounts.token_lottery.lottery_pot_amount = 0;
+        emit!(WinnerMarked {
+            lottery: token_lottery_key,
+            ticket_id,
+            tier: tier as u8,
+            slot: Clock::get()?.slot,
+        });
 
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-pub struct ClaimPrize<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>) -> Result<()> {
+        require!(
+            ctx.accounts.payer.key() == ctx.accounts.token_lottery.authority,
+            ErrorCode::NotAuthorized
+        );
 
-    #[account(
-        mut,
-        seeds = [b"token_lottery".as_ref()],
-        bump = token_lottery.bump,
-    )]
-    pub token_lottery: Account<'info, TokenLottery>,
+        // 払い戻しに備えて、当選者が決まるまで手数料は引き出せない
+        require!(ctx.accounts.token_lottery.winner_chosen, ErrorCode::WinnerNotChosen);
 
-    #[account(
-        mut,
-        seeds = [b"collection_mint".as_ref()],
-        bump,
-    )]
-    pub collection_mint: InterfaceAccount<'info, Mint>,
+        let amount = ctx.accounts.token_lottery.accrued_fees;
+        require!(amount > 0, ErrorCode::NoFeesAccrued);
 
-    #[account(
-        seeds = [token_lottery.winning_ticket_id.to_le_bytes().as_ref()],
-        bump,
-    )]
-    pub ticket_mint: InterfaceAccount<'info, Mint>,
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.treasury_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
 
-    #[account(
-        seeds = [b"metadata", token_metadata_program.key().as_ref(), ticket_mint.key().as_ref()],
-        bump,
-        seeds::program = token_metadata_program.key(),
-    )]
-    pub metadata: Account<'info, MetadataAccount>,
+        withdraw_from_pot(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.treasury.to_account_info(),
+            payment,
+            amount,
+        )?;
 
-    #[account(
-        associated_token::mint = ticket_mint,
-        associated_token::authority = payer,
-        associated_token::token_program = token_program,
-    )]
-    pub destination: InterfaceAccount<'info, TokenAccount>,
+        ctx.accounts.token_lottery.accrued_fees = 0;
 
-    #[account(
-        mut,
-        seeds = [b"metadata", token_metadata_program.key().as_ref(), collection_mint.key().as_ref()],
-        bump,
-        seeds::program = token_metadata_program.key(),
-    )]
-    pub collection_metadata: Account<'info, MetadataAccount>,
+        emit!(FeesWithdrawn {
+            lottery: ctx.accounts.token_lottery.key(),
+            treasury: ctx.accounts.treasury.key(),
+            amount,
+            slot: Clock::get()?.slot,
+        });
 
-    pub token_program: Interface<'info, TokenInterface>,
-    pub system_program: Program<'info, System>,
-    pub token_metadata_program: Program<'info, Metadata>,
-}
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct CommitWinner<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
+    pub fn cancel_lottery(ctx: Context<CancelLottery>) -> Result<()> {
+        let clock = Clock::get()?;
+        let token_lottery = &mut ctx.accounts.token_lottery;
 
-    #[account(
-        mut,
-        seeds = [b"token_lottery".as_ref()],
-        bump = token_lottery.bump,
-    )]
-    pub token_lottery: Account<'info, TokenLottery>,
+        let now = token_lottery.schedule_mode.now(&clock);
+        token_lottery.check_can_cancel(&ctx.accounts.payer.key(), now)?;
 
-    /// CHECK: The account's data is validated manually within the handler.
-    pub randomness_account_data: UncheckedAccount<'info>,
+        token_lottery.cancelled = true;
 
-    pub system_program: Program<'info, System>,
-}
+        msg!("Lottery {} cancelled", token_lottery.lottery_id);
 
-#[derive(Accounts)]
-pub struct RevealWinner<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
+        emit!(LotteryCancelled {
+            lottery: token_lottery.key(),
+            cancelled_by: ctx.accounts.payer.key(),
+            slot: clock.slot,
+        });
 
-    #[account(
-        mut,
-        seeds = [b"token_lottery".as_ref()],
-        bump = token_lottery.bump,
-    )]
-    pub token_lottery: Account<'info, TokenLottery>,
+        Ok(())
+    }
 
-    /// CHECK: The account's data is validated manually within the handler.
-    pub randomness_account_data: UncheckedAccount<'info>,
+    pub fn refund_ticket(ctx: Context<RefundTicket>, ticket_id: u64) -> Result<()> {
+        require!(ctx.accounts.token_lottery.cancelled, ErrorCode::LotteryNotCancelled);
+        ctx.accounts.ticket_record.validate(&ctx.accounts.token_lottery.key(), ticket_id, &ctx.accounts.ticket_mint.key())?;
+        require!(ctx.accounts.destination.amount == 1, ErrorCode::IncorrectTicket);
 
-    pub system_program: Program<'info, System>,
-}
+        // 1. Metaplex経由でチケットNFTをバーン（二重の払い戻しを防ぎ、レントを回収する）
+        let burn_nft_accounts = BurnNft {
+            metadata: ctx.accounts.metadata.to_account_info(),
+            owner: ctx.accounts.payer.to_account_info(),
+            mint: ctx.accounts.ticket_mint.to_account_info(),
+            token: ctx.accounts.destination.to_account_info(),
+            edition: ctx.accounts.master_edition.to_account_info(),
+            spl_token: ctx.accounts.token_program.to_account_info(),
+        };
 
-#[derive(Accounts)]
-pub struct BuyTicket<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
+        let burn_nft_cpi_context = CpiContext::new(
+            ctx.accounts.token_metadata_program.to_account_info(),
+            burn_nft_accounts,
+        ).with_remaining_accounts(vec![ctx.accounts.collection_metadata.to_account_info()]);
 
-    #[account(
-        mut,
-        seeds = [b"token_lottery".as_ref()],
-        bump = token_lottery.bump
-    )]
-    pub token_lottery: Account<'info, TokenLottery>,
+        burn_nft(burn_nft_cpi_context, Some(ctx.accounts.collection_metadata.key()))?;
 
-    #[account(
-        init,
-        payer = payer,
-        seeds = [token_lottery.total_tickets.to_le_bytes().as_ref()],
-        bump,
-        mint::decimals = 0,
-        mint::authority = collection_mint,
-        mint::freeze_authority = collection_mint,
-        mint::token_program = token_program
-    )]
-    pub ticket_mint: InterfaceAccount<'info, Mint>,
+        // 2. チケット代金を払い戻す（手数料分も返金する）
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
 
-    #[account(
-        init,
-        payer = payer,
-        associated_token::mint = ticket_mint,
-        associated_token::authority = payer,
-        associated_token::token_program = token_program,
-    )]
-    pub destination: InterfaceAccount<'info, TokenAccount>,
+        refund_from_pot(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.payer.to_account_info(),
+            payment,
+            ticket_id,
+            1,
+        )?;
+        ctx.accounts.token_lottery.record_ticket_closed()?;
 
-    #[account(
-        mut,
-        seeds = [b"metadata", token_metadata_program.key().as_ref(), 
-        ticket_mint.key().as_ref()],
-        bump,
-        seeds::program = token_metadata_program.key(),
-    )]
-    /// CHECK: This account will be initialized by the metaplex program
-    pub metadata: UncheckedAccount<'info>,
+        msg!("Refunded ticket {}", ticket_id);
 
-    #[account(
-        mut,
-        seeds = [b"metadata", token_metadata_program.key().as_ref(), 
-            ticket_mint.key().as_ref(), b"edition"],
-        bump,
-        seeds::program = token_metadata_program.key(),
-    )]
+        Ok(())
+    }
+
+    pub fn buy_tickets(ctx: Context<BuyTickets>, count: u64, entropy: Option<[u8; 32]>) -> Result<()> {
+        let clock = Clock::get()?;
+
+        require!(count > 0, ErrorCode::InvalidTicketCount);
+        ctx.accounts.token_lottery.check_sales_open(&clock)?;
+        let amount = ctx.accounts.token_lottery.price
+            .checked_mul(count)
+            .ok_or(ErrorCode::InvalidTicketCount)?;
+        ctx.accounts.token_lottery.check_ticket_limits(ctx.accounts.buyer_state.tickets_bought, count)?;
+
+        // 1. まとめてチケット代金を支払う
+
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
+
+        deposit_to_pot(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            payment,
+            amount,
+        )?;
+
+        ctx.accounts.token_lottery.record_sales(count)?;
+
+        // 2. 連番のチケットIDをレシートに記録（NFTはミントしない）
+        let first_ticket = ctx.accounts.token_lottery.total_tickets;
+        ctx.accounts.receipt.set_inner(TicketReceipt {
+            bump: ctx.bumps.receipt,
+            lottery: ctx.accounts.token_lottery.key(),
+            owner: ctx.accounts.payer.key(),
+            first_ticket,
+            count,
+            purchase_slot: clock.slot,
+        });
+
+        ctx.accounts.buyer_state.record_purchase(
+            ctx.bumps.buyer_state,
+            ctx.accounts.token_lottery.key(),
+            ctx.accounts.payer.key(),
+            count,
+        );
+        if let Some(entropy) = entropy {
+            ctx.accounts.token_lottery.mix_entropy(&ctx.accounts.payer.key(), &entropy);
+        }
+        ctx.accounts.token_lottery.total_tickets += count;
+
+        msg!("Tickets {}..{}", first_ticket, first_ticket + count);
+
+        emit!(TicketPurchased {
+            lottery: ctx.accounts.token_lottery.key(),
+            buyer: ctx.accounts.payer.key(),
+            first_ticket_id: first_ticket,
+            count,
+            amount,
+            slot: clock.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn claim_receipt_prize(ctx: Context<ClaimReceiptPrize>, first_ticket: u64, ticket_id: u64) -> Result<()> {
+        msg!("Receipt starting at ticket {}", first_ticket);
+
+        // Check if the ticket is part of the payer's receipt and won an unpaid prize tier
+        require_keys_eq!(ctx.accounts.receipt.owner, ctx.accounts.payer.key(), ErrorCode::NotAuthorized);
+        require!(ctx.accounts.receipt.contains(ticket_id), ErrorCode::IncorrectTicket);
+        let tier = ctx.accounts.token_lottery.claimable_tier(ticket_id)?;
+
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
+
+        pay_prize(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.payer.to_account_info(),
+            payment,
+            ticket_id,
+            tier,
+        )?;
+
+        // レシートの当選チケットをすべて受け取ったらレシートを閉じてレントを返す
+        if ctx.accounts.token_lottery.receipt_settled(&ctx.accounts.receipt) {
+            ctx.accounts.receipt.close(ctx.accounts.payer.to_account_info())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn close_receipt(ctx: Context<CloseReceipt>, first_ticket: u64) -> Result<()> {
+        let token_lottery = &ctx.accounts.token_lottery;
+        require!(token_lottery.winner_chosen || token_lottery.cancelled, ErrorCode::WinnerNotChosen);
+        require_keys_eq!(ctx.accounts.receipt.owner, ctx.accounts.payer.key(), ErrorCode::NotAuthorized);
+
+        // 当選チケットを含むレシートは賞金を受け取るまで閉じられない
+        require!(token_lottery.receipt_settled(&ctx.accounts.receipt), ErrorCode::UnclaimedPrize);
+
+        // キャンセルされた抽選のレシートは、閉じる前にチケット代金を払い戻す
+        if token_lottery.cancelled {
+            let count = ctx.accounts.receipt.count;
+
+            let payment = token_payment(
+                &ctx.accounts.token_lottery,
+                &ctx.accounts.payment_mint,
+                &ctx.accounts.vault,
+                &ctx.accounts.payer_token_account,
+                &ctx.accounts.payment_token_program,
+            )?;
+
+            refund_from_pot(
+                &mut ctx.accounts.token_lottery,
+                &ctx.accounts.payer.to_account_info(),
+                payment,
+                first_ticket,
+                count,
+            )?;
+        }
+
+        msg!("Closed receipt starting at ticket {}", first_ticket);
+
+        Ok(())
+    }
+
+    pub fn refund_receipt(ctx: Context<RefundReceipt>, first_ticket: u64) -> Result<()> {
+        require!(ctx.accounts.token_lottery.cancelled, ErrorCode::LotteryNotCancelled);
+        require_keys_eq!(ctx.accounts.receipt.owner, ctx.accounts.payer.key(), ErrorCode::NotAuthorized);
+
+        // レシートはこの命令の終了時にクローズされるので二重の払い戻しはできない
+        let count = ctx.accounts.receipt.count;
+
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
+
+        refund_from_pot(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.payer.to_account_info(),
+            payment,
+            first_ticket,
+            count,
+        )?;
+
+        msg!("Refunded tickets {}..{}", first_ticket, first_ticket + count);
+
+        Ok(())
+    }
+
+    pub fn close_lottery(ctx: Context<CloseLottery>) -> Result<()> {
+        require!(
+            ctx.accounts.payer.key() == ctx.accounts.token_lottery.authority,
+            ErrorCode::NotAuthorized
+        );
+        // 賞金と手数料をすべて支払った後、またはキャンセル後に全額払い戻した後のみ閉じられる
+        require!(ctx.accounts.token_lottery.is_settled(), ErrorCode::LotteryNotSettled);
+        // チケットレコードのレントが失われないよう、すべて閉じられるまで待つ
+        require!(ctx.accounts.token_lottery.open_tickets == 0, ErrorCode::TicketsStillOpen);
+
+        let token_lottery_key = ctx.accounts.token_lottery.key();
+        let creator = ctx.accounts.token_lottery.creator;
+        let lottery_id = ctx.accounts.token_lottery.lottery_id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"token_lottery".as_ref(),
+            creator.as_ref(),
+            lottery_id.as_ref(),
+            &[ctx.accounts.token_lottery.bump],
+        ]];
+
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
+
+        // トークン建ての場合は端数を回収してからvaultを閉じる
+        if let Some(payment) = payment {
+            let dust = payment.vault.amount;
+            if dust > 0 {
+                withdraw_from_pot(
+                    &ctx.accounts.token_lottery,
+                    &ctx.accounts.payer.to_account_info(),
+                    Some(payment),
+                    dust,
+                )?;
+            }
+
+            let close_accounts = CloseAccount {
+                account: payment.vault.to_account_info(),
+                destination: ctx.accounts.payer.to_account_info(),
+                authority: ctx.accounts.token_lottery.to_account_info(),
+            };
+
+            let close_cpi_context = CpiContext::new(
+                payment.token_program.to_account_info(),
+                close_accounts,
+            ).with_signer(signer_seeds);
+
+            close_account(close_cpi_context)?;
+        }
+
+        // コレクションNFTをバーンし、メタデータ・エディション・トークンアカウントのレントを回収する。
+        // 圧縮チケットや凍結した当選チケットが残るコレクションはバーンできないので、その場合は渡さない
+        if let Some(collection_mint) = &ctx.accounts.collection_mint {
+            let (
+                Some(collection_token_account),
+                Some(collection_metadata),
+                Some(collection_master_edition),
+                Some(token_program),
+                Some(token_metadata_program),
+            ) = (
+                &ctx.accounts.collection_token_account,
+                &ctx.accounts.collection_metadata,
+                &ctx.accounts.collection_master_edition,
+                &ctx.accounts.token_program,
+                &ctx.accounts.token_metadata_program,
+            ) else {
+                return err!(ErrorCode::MissingCollectionAccounts);
+            };
+
+            // コレクショントークンアカウントは自分自身が所有者なので、先に所有者を抽選PDAに移す
+            let (_, collection_token_account_bump) = Pubkey::find_program_address(
+                &[b"collection_token_account".as_ref(), token_lottery_key.as_ref()],
+                &ID,
+            );
+            let token_account_seeds: &[&[&[u8]]] = &[&[
+                b"collection_token_account".as_ref(),
+                token_lottery_key.as_ref(),
+                &[collection_token_account_bump],
+            ]];
+
+            let set_authority_accounts = SetAuthority {
+                current_authority: collection_token_account.to_account_info(),
+                account_or_mint: collection_token_account.to_account_info(),
+            };
+
+            set_authority(
+                CpiContext::new(token_program.to_account_info(), set_authority_accounts)
+                    .with_signer(token_account_seeds),
+                AuthorityType::AccountOwner,
+                Some(token_lottery_key),
+            )?;
+
+            // 回収したレントは抽選アカウントに入り、close = payer でまとめて返却される。
+            // レガシーSPLのミントは閉じられないため、ミント自体は供給量0で残る
+            let burn_nft_accounts = BurnNft {
+                metadata: collection_metadata.to_account_info(),
+                owner: ctx.accounts.token_lottery.to_account_info(),
+                mint: collection_mint.to_account_info(),
+                token: collection_token_account.to_account_info(),
+                edition: collection_master_edition.to_account_info(),
+                spl_token: token_program.to_account_info(),
+            };
+
+            burn_nft(
+                CpiContext::new(token_metadata_program.to_account_info(), burn_nft_accounts)
+                    .with_signer(signer_seeds),
+                None,
+            )?;
+        }
+
+        // 残りのlamports（レントと端数）は close = payer で返却される
+        emit!(LotteryClosed {
+            lottery: ctx.accounts.token_lottery.key(),
+            authority: ctx.accounts.payer.key(),
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn close_ticket(ctx: Context<CloseTicket>, ticket_id: u64) -> Result<()> {
+        let token_lottery = &ctx.accounts.token_lottery;
+        require!(token_lottery.winner_chosen || token_lottery.cancelled, ErrorCode::WinnerNotChosen);
+        ctx.accounts.ticket_record.validate(&token_lottery.key(), ticket_id, &ctx.accounts.ticket_mint.key())?;
+        require!(ctx.accounts.destination.amount == 1, ErrorCode::IncorrectTicket);
+
+        // 当選チケットは賞金を受け取るまで閉じられない
+        if let Some(tier) = token_lottery.winning_tier(ticket_id) {
+            require!(token_lottery.is_tier_claimed(tier), ErrorCode::UnclaimedPrize);
+        }
+
+        // Metaplex経由でバーンし、メタデータ・エディション・ATAのレントを回収する
+        let burn_nft_accounts = BurnNft {
+            metadata: ctx.accounts.metadata.to_account_info(),
+            owner: ctx.accounts.payer.to_account_info(),
+            mint: ctx.accounts.ticket_mint.to_account_info(),
+            token: ctx.accounts.destination.to_account_info(),
+            edition: ctx.accounts.master_edition.to_account_info(),
+            spl_token: ctx.accounts.token_program.to_account_info(),
+        };
+
+        let burn_nft_cpi_context = CpiContext::new(
+            ctx.accounts.token_metadata_program.to_account_info(),
+            burn_nft_accounts,
+        ).with_remaining_accounts(vec![ctx.accounts.collection_metadata.to_account_info()]);
+
+        burn_nft(burn_nft_cpi_context, Some(ctx.accounts.collection_metadata.key()))?;
+
+        // キャンセルされた抽選のチケットは、閉じる前にチケット代金を払い戻す
+        if ctx.accounts.token_lottery.cancelled {
+            let payment = token_payment(
+                &ctx.accounts.token_lottery,
+                &ctx.accounts.payment_mint,
+                &ctx.accounts.vault,
+                &ctx.accounts.payer_token_account,
+                &ctx.accounts.payment_token_program,
+            )?;
+
+            refund_from_pot(
+                &mut ctx.accounts.token_lottery,
+                &ctx.accounts.payer.to_account_info(),
+                payment,
+                ticket_id,
+                1,
+            )?;
+        }
+
+        ctx.accounts.token_lottery.record_ticket_closed()?;
+
+        emit!(TicketClosed {
+            lottery: ctx.accounts.token_lottery.key(),
+            owner: ctx.accounts.payer.key(),
+            ticket_id,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_config(ctx: Context<UpdateConfig>, params: UpdateConfigParams) -> Result<()> {
+        let token_lottery = &mut ctx.accounts.token_lottery;
+        require!(
+            ctx.accounts.payer.key() == token_lottery.authority,
+            ErrorCode::NotAuthorized
+        );
+
+        // 販売開始後は購入者の条件に関わる項目を変更できない
+        let changes_sale_terms = params.start.is_some() ||
+            params.end.is_some() ||
+            params.price.is_some() ||
+            params.fee_bps.is_some() ||
+            params.prize_tiers.is_some() ||
+            params.cancel_timeout.is_some() ||
+            params.winning_ticket_action.is_some();
+        if changes_sale_terms {
+            token_lottery.check_sale_terms_unlocked(&Clock::get()?)?;
+        }
+
+        if let Some(start) = params.start {
+            token_lottery.lottery_start = start;
+        }
+        if let Some(end) = params.end {
+            token_lottery.lottery_end = end;
+        }
+        if params.start.is_some() || params.end.is_some() {
+            token_lottery.validate_schedule(&Clock::get()?)?;
+        }
+        if let Some(price) = params.price {
+            token_lottery.price = price;
+        }
+        if let Some(fee_bps) = params.fee_bps {
+            require!(fee_bps <= MAX_FEE_BPS, ErrorCode::InvalidFee);
+            token_lottery.fee_bps = fee_bps;
+        }
+        if let Some(prize_tiers) = params.prize_tiers {
+            validate_prize_tiers(&prize_tiers)?;
+            token_lottery.prize_tiers = prize_tiers;
+        }
+        if let Some(cancel_timeout) = params.cancel_timeout {
+            token_lottery.cancel_timeout = cancel_timeout;
+        }
+        if let Some(winning_ticket_action) = params.winning_ticket_action {
+            token_lottery.winning_ticket_action = winning_ticket_action;
+            token_lottery.validate_ticket_mode()?;
+        }
+        if let Some(treasury) = params.treasury {
+            token_lottery.treasury = treasury;
+        }
+        if let Some(crank_reward) = params.crank_reward {
+            token_lottery.crank_reward = crank_reward;
+        }
+
+        emit!(ConfigUpdated {
+            lottery: token_lottery.key(),
+            authority: token_lottery.authority,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn propose_authority(ctx: Context<ProposeAuthority>, new_authority: Option<Pubkey>) -> Result<()> {
+        let token_lottery = &mut ctx.accounts.token_lottery;
+        require!(
+            ctx.accounts.payer.key() == token_lottery.authority,
+            ErrorCode::NotAuthorized
+        );
+
+        // None を指定すると提案を取り消す
+        token_lottery.pending_authority = new_authority;
+
+        emit!(AuthorityProposed {
+            lottery: token_lottery.key(),
+            authority: token_lottery.authority,
+            pending_authority: new_authority,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let token_lottery = &mut ctx.accounts.token_lottery;
+        require!(
+            token_lottery.pending_authority == Some(ctx.accounts.payer.key()),
+            ErrorCode::NotAuthorized
+        );
+
+        let previous_authority = token_lottery.authority;
+        token_lottery.authority = ctx.accounts.payer.key();
+        token_lottery.pending_authority = None;
+
+        emit!(AuthorityTransferred {
+            lottery: token_lottery.key(),
+            previous_authority,
+            authority: token_lottery.authority,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_paused(ctx: Context<SetPaused>, paused: bool) -> Result<()> {
+        let token_lottery = &mut ctx.accounts.token_lottery;
+        require!(
+            ctx.accounts.payer.key() == token_lottery.authority,
+            ErrorCode::NotAuthorized
+        );
+
+        token_lottery.paused = paused;
+
+        msg!("Lottery {} paused: {}", token_lottery.lottery_id, paused);
+
+        emit!(PausedSet {
+            lottery: token_lottery.key(),
+            paused,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn initialize_ticket_tree(ctx: Context<InitializeTicketTree>, max_depth: u32, max_buffer_size: u32) -> Result<()> {
+        let token_lottery = &ctx.accounts.token_lottery;
+        require!(
+            ctx.accounts.payer.key() == token_lottery.authority,
+            ErrorCode::NotAuthorized
+        );
+        require!(token_lottery.ticket_mode == TicketMode::Compressed, ErrorCode::InvalidTicketMode);
+        require!(token_lottery.merkle_tree == Pubkey::default(), ErrorCode::TicketTreeAlreadyInitialized);
+
+        let creator = token_lottery.creator;
+        let lottery_id = token_lottery.lottery_id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"token_lottery".as_ref(),
+            creator.as_ref(),
+            lottery_id.as_ref(),
+            &[token_lottery.bump],
+        ]];
+
+        // 1. 抽選PDAをツリー作成者としてBubblegumのツリー設定を作成（非公開ツリーなので抽選経由でしかミントできない）
+        let token_lottery_info = token_lottery.to_account_info();
+        CreateTreeConfigCpi::new(
+            &ctx.accounts.bubblegum_program,
+            CreateTreeConfigCpiAccounts {
+                tree_config: &ctx.accounts.tree_config,
+                merkle_tree: &ctx.accounts.merkle_tree,
+                payer: &ctx.accounts.payer,
+                tree_creator: &token_lottery_info,
+                log_wrapper: &ctx.accounts.log_wrapper,
+                compression_program: &ctx.accounts.compression_program,
+                system_program: &ctx.accounts.system_program,
+            },
+            CreateTreeConfigInstructionArgs {
+                max_depth,
+                max_buffer_size,
+                public: Some(false),
+            },
+        ).invoke_signed(signer_seeds)?;
+
+        // 2. ツリーを記録
+        let token_lottery = &mut ctx.accounts.token_lottery;
+        token_lottery.merkle_tree = ctx.accounts.merkle_tree.key();
+
+        emit!(TicketTreeInitialized {
+            lottery: token_lottery.key(),
+            merkle_tree: token_lottery.merkle_tree,
+            max_depth,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn buy_compressed_ticket(ctx: Context<BuyCompressedTicket>, entropy: Option<[u8; 32]>) -> Result<()> {
+        let clock = Clock::get()?;
+        let ticket_id = ctx.accounts.token_lottery.total_tickets;
+
+        require!(
+            ctx.accounts.token_lottery.ticket_mode == TicketMode::Compressed,
+            ErrorCode::InvalidTicketMode
+        );
+        ctx.accounts.token_lottery.check_sales_open(&clock)?;
+        ctx.accounts.token_lottery.check_ticket_limits(ctx.accounts.buyer_state.tickets_bought, 1)?;
+
+        // 1. チケット代金を支払う
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
+
+        deposit_to_pot(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            payment,
+            ctx.accounts.token_lottery.price,
+        )?;
+
+        ctx.accounts.token_lottery.record_sales(1)?;
+
+        // 2. 圧縮NFTとしてチケットをミント（ツリー作成者の抽選PDAとコレクション権限のcollection_mintが署名）
+        let metadata = compressed_ticket_metadata(
+            &ctx.accounts.token_lottery.metadata,
+            ctx.accounts.collection_mint.key(),
+            ticket_id,
+            false,  // verified: コレクションの検証はBubblegumがミント時に行う
+        )?;
+
+        let token_lottery_key = ctx.accounts.token_lottery.key();
+        let creator = ctx.accounts.token_lottery.creator;
+        let lottery_id = ctx.accounts.token_lottery.lottery_id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[
+            &[
+                b"token_lottery".as_ref(),
+                creator.as_ref(),
+                lottery_id.as_ref(),
+                &[ctx.accounts.token_lottery.bump],
+            ],
+            &[
+                b"collection_mint".as_ref(),
+                token_lottery_key.as_ref(),
+                &[ctx.bumps.collection_mint],
+            ],
+        ];
+
+        let token_lottery_info = ctx.accounts.token_lottery.to_account_info();
+        let collection_mint_info = ctx.accounts.collection_mint.to_account_info();
+        MintToCollectionV1Cpi::new(
+            &ctx.accounts.bubblegum_program,
+            MintToCollectionV1CpiAccounts {
+                tree_config: &ctx.accounts.tree_config,
+                leaf_owner: &ctx.accounts.payer,
+                leaf_delegate: &ctx.accounts.payer,
+                merkle_tree: &ctx.accounts.merkle_tree,
+                payer: &ctx.accounts.payer,
+                tree_creator_or_delegate: &token_lottery_info,
+                collection_authority: &collection_mint_info,
+                collection_authority_record_pda: None,
+                collection_mint: &collection_mint_info,
+                collection_metadata: &ctx.accounts.collection_metadata,
+                collection_edition: &ctx.accounts.collection_master_edition,
+                bubblegum_signer: &ctx.accounts.bubblegum_signer,
+                log_wrapper: &ctx.accounts.log_wrapper,
+                compression_program: &ctx.accounts.compression_program,
+                token_metadata_program: &ctx.accounts.token_metadata_program,
+                system_program: &ctx.accounts.system_program,
+            },
+            MintToCollectionV1InstructionArgs { metadata },
+        ).invoke_signed(signer_seeds)?;
+
+        ctx.accounts.buyer_state.record_purchase(
+            ctx.bumps.buyer_state,
+            ctx.accounts.token_lottery.key(),
+            ctx.accounts.payer.key(),
+            1,
+        );
+        if let Some(entropy) = entropy {
+            ctx.accounts.token_lottery.mix_entropy(&ctx.accounts.payer.key(), &entropy);
+        }
+
+        emit!(TicketPurchased {
+            lottery: ctx.accounts.token_lottery.key(),
+            buyer: ctx.accounts.payer.key(),
+            first_ticket_id: ticket_id,
+            count: 1,
+            amount: ctx.accounts.token_lottery.price,
+            slot: clock.slot,
+        });
+
+        ctx.accounts.token_lottery.total_tickets += 1;
+
+        Ok(())
+    }
+
+    pub fn claim_compressed_prize<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClaimCompressedPrize<'info>>,
+        ticket_id: u64,
+        root: [u8; 32],
+        nonce: u64,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.token_lottery.ticket_mode == TicketMode::Compressed,
+            ErrorCode::InvalidTicketMode
+        );
+        // Check if the ticket won a prize tier that has not been paid yet
+        let tier = ctx.accounts.token_lottery.claimable_tier(ticket_id)?;
+
+        // Check if the winner owns the leaf minted for this ticket. The leaf's data hash
+        // commits to the ticket name and the verified collection, so it cannot be forged.
+        let leaf = compressed_ticket_leaf(
+            &ctx.accounts.token_lottery.metadata,
+            ctx.accounts.collection_mint.key(),
+            ticket_id,
+            &ctx.accounts.merkle_tree.key(),
+            nonce,
+            ctx.accounts.payer.key(),
+            ctx.accounts.leaf_delegate.key(),
+        )?;
+        verify_ticket_leaf(
+            &ctx.accounts.compression_program,
+            &ctx.accounts.merkle_tree,
+            ctx.remaining_accounts,
+            root,
+            leaf,
+            leaf_index(nonce)?,
+        )?;
+
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
+
+        pay_prize(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.payer.to_account_info(),
+            payment,
+            ticket_id,
+            tier,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn refund_compressed_ticket<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RefundCompressedTicket<'info>>,
+        ticket_id: u64,
+        root: [u8; 32],
+        nonce: u64,
+    ) -> Result<()> {
+        require!(ctx.accounts.token_lottery.cancelled, ErrorCode::LotteryNotCancelled);
+        require!(
+            ctx.accounts.token_lottery.ticket_mode == TicketMode::Compressed,
+            ErrorCode::InvalidTicketMode
+        );
+
+        // 1. 圧縮チケットをバーン（二重の払い戻しを防ぐ）
+        let data_hash = compressed_ticket_data_hash(
+            &ctx.accounts.token_lottery.metadata,
+            ctx.accounts.collection_mint.key(),
+            ticket_id,
+        )?;
+        let proof: Vec<_> = ctx.remaining_accounts.iter()
+            .map(|node| (node, false, false))
+            .collect();
+
+        BurnCpi::new(
+            &ctx.accounts.bubblegum_program,
+            BurnCpiAccounts {
+                tree_config: &ctx.accounts.tree_config,
+                leaf_owner: (&ctx.accounts.payer, true),
+                leaf_delegate: (&ctx.accounts.leaf_delegate, false),
+                merkle_tree: &ctx.accounts.merkle_tree,
+                log_wrapper: &ctx.accounts.log_wrapper,
+                compression_program: &ctx.accounts.compression_program,
+                system_program: &ctx.accounts.system_program,
+            },
+            BurnInstructionArgs {
+                root,
+                data_hash,
+                creator_hash: hash_creators(&[]),
+                nonce,
+                index: leaf_index(nonce)?,
+            },
+        ).invoke_with_remaining_accounts(&proof)?;
+
+        // 2. チケット代金を払い戻す（手数料分も返金する）
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
+
+        refund_from_pot(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.payer.to_account_info(),
+            payment,
+            ticket_id,
+            1,
+        )?;
+
+        msg!("Refunded compressed ticket {}", ticket_id);
+
+        Ok(())
+    }
+
+    pub fn initialize_token_group(ctx: Context<InitializeTokenGroup>) -> Result<()> {
+        let token_lottery = &ctx.accounts.token_lottery;
+        require!(
+            ctx.accounts.payer.key() == token_lottery.authority,
+            ErrorCode::NotAuthorized
+        );
+        require!(token_lottery.ticket_mode == TicketMode::Token2022, ErrorCode::InvalidTicketMode);
+
+        let token_lottery_key = token_lottery.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"collection_mint".as_ref(),
+            token_lottery_key.as_ref(),
+            &[ctx.bumps.collection_mint],
+        ]];
+
+        let collection_mint = ctx.accounts.collection_mint.to_account_info();
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let metadata = TokenMetadata {
+            update_authority: OptionalNonZeroPubkey::try_from(Some(collection_mint.key()))?,
+            mint: collection_mint.key(),
+            name: token_lottery.metadata.name_prefix.clone(),
+            symbol: token_lottery.metadata.symbol.clone(),
+            uri: token_lottery.metadata.collection_uri.clone(),
+            additional_metadata: vec![],
+        };
+
+        // 1. メタデータとグループの分だけミントアカウントのレントを補填
+        fund_mint_extensions(
+            &ctx.accounts.payer,
+            &collection_mint,
+            &ctx.accounts.system_program,
+            &[ExtensionType::MetadataPointer, ExtensionType::GroupPointer, ExtensionType::TokenGroup],
+            &metadata,
+        )?;
+
+        // 2. コレクションミント自身にメタデータを書き込む
+        let metadata_initialize_accounts = TokenMetadataInitialize {
+            program_id: token_program.clone(),
+            metadata: collection_mint.clone(),
+            update_authority: collection_mint.clone(),
+            mint_authority: collection_mint.clone(),
+            mint: collection_mint.clone(),
+        };
+
+        token_metadata_initialize(
+            CpiContext::new(token_program.clone(), metadata_initialize_accounts).with_signer(signer_seeds),
+            metadata.name,
+            metadata.symbol,
+            metadata.uri,
+        )?;
+
+        // 3. コレクションミントをトークングループとして初期化（上限はチケットの最大枚数）
+        let group_initialize_accounts = TokenGroupInitialize {
+            program_id: token_program.clone(),
+            group: collection_mint.clone(),
+            mint: collection_mint.clone(),
+            mint_authority: collection_mint.clone(),
+        };
+
+        token_group_initialize(
+            CpiContext::new(token_program, group_initialize_accounts).with_signer(signer_seeds),
+            Some(collection_mint.key()),  // update_authority: メンバー追加時にcollection_mintが署名する
+            token_lottery.max_tickets.unwrap_or(u64::MAX),
+        )?;
+
+        emit!(LotteryInitialized {
+            lottery: token_lottery_key,
+            collection_mint: collection_mint.key(),
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn buy_token2022_ticket(ctx: Context<BuyToken2022Ticket>, entropy: Option<[u8; 32]>) -> Result<()> {
+        let clock = Clock::get()?;
+        let ticket_id = ctx.accounts.token_lottery.total_tickets;
+
+        require!(
+            ctx.accounts.token_lottery.ticket_mode == TicketMode::Token2022,
+            ErrorCode::InvalidTicketMode
+        );
+        ctx.accounts.token_lottery.check_sales_open(&clock)?;
+        ctx.accounts.token_lottery.check_ticket_limits(ctx.accounts.buyer_state.tickets_bought, 1)?;
+
+        // 1. チケット代金を支払う
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
+
+        deposit_to_pot(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            payment,
+            ctx.accounts.token_lottery.price,
+        )?;
+
+        ctx.accounts.token_lottery.record_sales(1)?;
+
+        let token_lottery_key = ctx.accounts.token_lottery.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"collection_mint".as_ref(),
+            token_lottery_key.as_ref(),
+            &[ctx.bumps.collection_mint],
+        ]];
+
+        let ticket_mint = ctx.accounts.ticket_mint.to_account_info();
+        let collection_mint = ctx.accounts.collection_mint.to_account_info();
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let metadata = TokenMetadata {
+            update_authority: OptionalNonZeroPubkey::try_from(Some(collection_mint.key()))?,
+            mint: ticket_mint.key(),
+            name: ctx.accounts.token_lottery.metadata.ticket_name(ticket_id)?,
+            symbol: ctx.accounts.token_lottery.metadata.symbol.clone(),
+            uri: ctx.accounts.token_lottery.metadata.ticket_uri(ticket_id)?,
+            additional_metadata: vec![],
+        };
+
+        // 2. メタデータとグループメンバーの分だけミントアカウントのレントを補填
+        fund_mint_extensions(
+            &ctx.accounts.payer,
+            &ticket_mint,
+            &ctx.accounts.system_program,
+            &[
+                ExtensionType::MetadataPointer,
+                ExtensionType::GroupMemberPointer,
+                ExtensionType::TokenGroupMember,
+                ExtensionType::MintCloseAuthority,
+            ],
+            &metadata,
+        )?;
+
+        // 3. チケットミント自身にメタデータを書き込む
+        let metadata_initialize_accounts = TokenMetadataInitialize {
+            program_id: token_program.clone(),
+            metadata: ticket_mint.clone(),
+            update_authority: collection_mint.clone(),
+            mint_authority: collection_mint.clone(),
+            mint: ticket_mint.clone(),
+        };
+
+        token_metadata_initialize(
+            CpiContext::new(token_program.clone(), metadata_initialize_accounts).with_signer(signer_seeds),
+            metadata.name,
+            metadata.symbol,
+            metadata.uri,
+        )?;
+
+        // 4. チケットをコレクションのグループメンバーとして登録
+        let member_initialize_accounts = TokenMemberInitialize {
+            program_id: token_program.clone(),
+            member: ticket_mint.clone(),
+            member_mint: ticket_mint.clone(),
+            member_mint_authority: collection_mint.clone(),
+            group: collection_mint.clone(),
+            group_update_authority: collection_mint.clone(),
+        };
+
+        token_member_initialize(
+            CpiContext::new(token_program.clone(), member_initialize_accounts).with_signer(signer_seeds),
+        )?;
+
+        // 5. チケットをミントし、ミント権限を外して供給量を1に固定
+        let mint_to_accounts = MintTo {
+            mint: ticket_mint.clone(),
+            to: ctx.accounts.destination.to_account_info(),
+            authority: collection_mint.clone(),
+        };
+
+        mint_to(
+            CpiContext::new(token_program.clone(), mint_to_accounts).with_signer(signer_seeds),
+            1,
+        )?;
+
+        let set_authority_accounts = SetAuthority {
+            current_authority: collection_mint,
+            account_or_mint: ticket_mint,
+        };
+
+        set_authority(
+            CpiContext::new(token_program, set_authority_accounts).with_signer(signer_seeds),
+            AuthorityType::MintTokens,
+            None,
+        )?;
+
+        // 6. チケットの記録を保存
+        ctx.accounts.ticket_record.set_inner(TicketRecord {
+            bump: ctx.bumps.ticket_record,
+            lottery: token_lottery_key,
+            ticket_index: ticket_id,
+            mint: ctx.accounts.ticket_mint.key(),
+            purchase_slot: clock.slot,
+        });
+
+        ctx.accounts.buyer_state.record_purchase(
+            ctx.bumps.buyer_state,
+            token_lottery_key,
+            ctx.accounts.payer.key(),
+            1,
+        );
+        if let Some(entropy) = entropy {
+            ctx.accounts.token_lottery.mix_entropy(&ctx.accounts.payer.key(), &entropy);
+        }
+
+        emit!(TicketPurchased {
+            lottery: token_lottery_key,
+            buyer: ctx.accounts.payer.key(),
+            first_ticket_id: ticket_id,
+            count: 1,
+            amount: ctx.accounts.token_lottery.price,
+            slot: clock.slot,
+        });
+
+        ctx.accounts.token_lottery.total_tickets += 1;
+        ctx.accounts.token_lottery.record_ticket_opened()?;
+
+        Ok(())
+    }
+
+    pub fn claim_token2022_prize(ctx: Context<ClaimToken2022Prize>, ticket_id: u64) -> Result<()> {
+        require!(
+            ctx.accounts.token_lottery.ticket_mode == TicketMode::Token2022,
+            ErrorCode::InvalidTicketMode
+        );
+        // Check if the ticket won a prize tier that has not been paid yet
+        let tier = ctx.accounts.token_lottery.claimable_tier(ticket_id)?;
+
+        // Check if the winner holds the ticket recorded at purchase. The ticket mint is a
+        // lottery PDA that joined the collection group when it was bought.
+        ctx.accounts.ticket_record.validate(&ctx.accounts.token_lottery.key(), ticket_id, &ctx.accounts.ticket_mint.key())?;
+        require!(ctx.accounts.destination.amount > 0, ErrorCode::IncorrectTicket);
+
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
+
+        pay_prize(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.payer.to_account_info(),
+            payment,
+            ticket_id,
+            tier,
+        )?;
+
+        // 当選チケットを設定どおりにバーンまたは凍結する（collection_mintがミントの凍結・クローズ権限）
+        let token_lottery_key = ctx.accounts.token_lottery.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"collection_mint".as_ref(),
+            token_lottery_key.as_ref(),
+            &[ctx.bumps.collection_mint],
+        ]];
+
+        retire_winning_ticket(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.ticket_record,
+            ctx.accounts.payer.to_account_info(),
+            || burn_token2022_ticket(
+                &ctx.accounts.token_program,
+                &ctx.accounts.ticket_mint,
+                &ctx.accounts.destination,
+                &ctx.accounts.payer,
+                &ctx.accounts.collection_mint,
+                signer_seeds,
+            ),
+            || {
+                let freeze_accounts = FreezeAccount {
+                    account: ctx.accounts.destination.to_account_info(),
+                    mint: ctx.accounts.ticket_mint.to_account_info(),
+                    authority: ctx.accounts.collection_mint.to_account_info(),
+                };
+
+                freeze_account(
+                    CpiContext::new(ctx.accounts.token_program.to_account_info(), freeze_accounts)
+                        .with_signer(signer_seeds),
+                )
+            },
+        )
+    }
+
+    pub fn refund_token2022_ticket(ctx: Context<RefundToken2022Ticket>, ticket_id: u64) -> Result<()> {
+        require!(ctx.accounts.token_lottery.cancelled, ErrorCode::LotteryNotCancelled);
+        require!(
+            ctx.accounts.token_lottery.ticket_mode == TicketMode::Token2022,
+            ErrorCode::InvalidTicketMode
+        );
+        ctx.accounts.ticket_record.validate(&ctx.accounts.token_lottery.key(), ticket_id, &ctx.accounts.ticket_mint.key())?;
+        require!(ctx.accounts.destination.amount == 1, ErrorCode::IncorrectTicket);
+
+        // 1. チケットをバーンしてATAとミントを閉じる（二重の払い戻しを防ぐ）
+        let token_lottery_key = ctx.accounts.token_lottery.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"collection_mint".as_ref(),
+            token_lottery_key.as_ref(),
+            &[ctx.bumps.collection_mint],
+        ]];
+
+        burn_token2022_ticket(
+            &ctx.accounts.token_program,
+            &ctx.accounts.ticket_mint,
+            &ctx.accounts.destination,
+            &ctx.accounts.payer,
+            &ctx.accounts.collection_mint,
+            signer_seeds,
+        )?;
+
+        // 2. チケット代金を払い戻す（手数料分も返金する）
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
+
+        refund_from_pot(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.payer.to_account_info(),
+            payment,
+            ticket_id,
+            1,
+        )?;
+        ctx.accounts.token_lottery.record_ticket_closed()?;
+
+        msg!("Refunded Token-2022 ticket {}", ticket_id);
+
+        Ok(())
+    }
+
+    pub fn close_token2022_ticket(ctx: Context<CloseToken2022Ticket>, ticket_id: u64) -> Result<()> {
+        let token_lottery = &ctx.accounts.token_lottery;
+        require!(token_lottery.ticket_mode == TicketMode::Token2022, ErrorCode::InvalidTicketMode);
+        require!(token_lottery.winner_chosen || token_lottery.cancelled, ErrorCode::WinnerNotChosen);
+        ctx.accounts.ticket_record.validate(&token_lottery.key(), ticket_id, &ctx.accounts.ticket_mint.key())?;
+        require!(ctx.accounts.destination.amount == 1, ErrorCode::IncorrectTicket);
+
+        // 当選チケットは賞金を受け取るまで閉じられない
+        if let Some(tier) = token_lottery.winning_tier(ticket_id) {
+            require!(token_lottery.is_tier_claimed(tier), ErrorCode::UnclaimedPrize);
+        }
+
+        // Token-2022でバーンし、ATAとミントのレントを回収する
+        let token_lottery_key = token_lottery.key();
+        let signer_seeds: &[&[&[u8]]] = &[&[
+            b"collection_mint".as_ref(),
+            token_lottery_key.as_ref(),
+            &[ctx.bumps.collection_mint],
+        ]];
+
+        burn_token2022_ticket(
+            &ctx.accounts.token_program,
+            &ctx.accounts.ticket_mint,
+            &ctx.accounts.destination,
+            &ctx.accounts.payer,
+            &ctx.accounts.collection_mint,
+            signer_seeds,
+        )?;
+
+        // キャンセルされた抽選のチケットは、閉じる前にチケット代金を払い戻す
+        if ctx.accounts.token_lottery.cancelled {
+            let payment = token_payment(
+                &ctx.accounts.token_lottery,
+                &ctx.accounts.payment_mint,
+                &ctx.accounts.vault,
+                &ctx.accounts.payer_token_account,
+                &ctx.accounts.payment_token_program,
+            )?;
+
+            refund_from_pot(
+                &mut ctx.accounts.token_lottery,
+                &ctx.accounts.payer.to_account_info(),
+                payment,
+                ticket_id,
+                1,
+            )?;
+        }
+
+        close_ticket_record(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.ticket_record,
+            ctx.accounts.payer.to_account_info(),
+        )?;
+
+        emit!(TicketClosed {
+            lottery: token_lottery_key,
+            owner: ctx.accounts.payer.key(),
+            ticket_id,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+
+    pub fn buy_core_ticket(ctx: Context<BuyCoreTicket>, entropy: Option<[u8; 32]>) -> Result<()> {
+        let clock = Clock::get()?;
+        let ticket_id = ctx.accounts.token_lottery.total_tickets;
+
+        require!(
+            ctx.accounts.token_lottery.ticket_mode == TicketMode::Core,
+            ErrorCode::InvalidTicketMode
+        );
+        ctx.accounts.token_lottery.check_sales_open(&clock)?;
+        ctx.accounts.token_lottery.check_ticket_limits(ctx.accounts.buyer_state.tickets_bought, 1)?;
+
+        // 1. チケット代金を支払う
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
+
+        deposit_to_pot(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+            payment,
+            ctx.accounts.token_lottery.price,
+        )?;
+
+        ctx.accounts.token_lottery.record_sales(1)?;
+
+        // 2. Coreアセットとしてチケットをミント（アセットPDAとコレクション権限の抽選PDAが署名）
+        let token_lottery_key = ctx.accounts.token_lottery.key();
+        let ticket_id_bytes = ticket_id.to_le_bytes();
+        let creator = ctx.accounts.token_lottery.creator;
+        let lottery_id = ctx.accounts.token_lottery.lottery_id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[
+            &[
+                TICKET_MINT_SEED,
+                token_lottery_key.as_ref(),
+                ticket_id_bytes.as_ref(),
+                &[ctx.bumps.ticket_asset],
+            ],
+            &[
+                b"token_lottery".as_ref(),
+                creator.as_ref(),
+                lottery_id.as_ref(),
+                &[ctx.accounts.token_lottery.bump],
+            ],
+        ];
+
+        // 抽選・チケット番号・購入スロットはAttributesプラグインに保存
+        let attributes = Attributes {
+            attribute_list: vec![
+                Attribute { key: "lottery".to_string(), value: token_lottery_key.to_string() },
+                Attribute { key: "ticket_index".to_string(), value: ticket_id.to_string() },
+                Attribute { key: "purchase_slot".to_string(), value: clock.slot.to_string() },
+            ],
+        };
+
+        let token_lottery = ctx.accounts.token_lottery.to_account_info();
+        CreateV2Cpi::new(
+            &ctx.accounts.mpl_core_program,
+            CreateV2CpiAccounts {
+                asset: &ctx.accounts.ticket_asset,
+                collection: Some(&ctx.accounts.core_collection),
+                authority: Some(&token_lottery),
+                payer: &ctx.accounts.payer,
+                owner: Some(&ctx.accounts.payer),
+                update_authority: None,  // コレクションの更新権限を引き継ぐ
+                system_program: &ctx.accounts.system_program,
+                log_wrapper: None,
+            },
+            CreateV2InstructionArgs {
+                data_state: DataState::AccountState,
+                name: ctx.accounts.token_lottery.metadata.ticket_name(ticket_id)?,
+                uri: ctx.accounts.token_lottery.metadata.ticket_uri(ticket_id)?,
+                plugins: Some(vec![PluginAuthorityPair {
+                    plugin: Plugin::Attributes(attributes),
+                    authority: Some(PluginAuthority::UpdateAuthority),
+                }]),
+                external_plugin_adapters: None,
+            },
+        ).invoke_signed(signer_seeds)?;
+
+        // 3. チケットの記録を保存
+        ctx.accounts.ticket_record.set_inner(TicketRecord {
+            bump: ctx.bumps.ticket_record,
+            lottery: token_lottery_key,
+            ticket_index: ticket_id,
+            mint: ctx.accounts.ticket_asset.key(),
+            purchase_slot: clock.slot,
+        });
+
+        ctx.accounts.buyer_state.record_purchase(
+            ctx.bumps.buyer_state,
+            token_lottery_key,
+            ctx.accounts.payer.key(),
+            1,
+        );
+        if let Some(entropy) = entropy {
+            ctx.accounts.token_lottery.mix_entropy(&ctx.accounts.payer.key(), &entropy);
+        }
+
+        emit!(TicketPurchased {
+            lottery: token_lottery_key,
+            buyer: ctx.accounts.payer.key(),
+            first_ticket_id: ticket_id,
+            count: 1,
+            amount: ctx.accounts.token_lottery.price,
+            slot: clock.slot,
+        });
+
+        ctx.accounts.token_lottery.total_tickets += 1;
+        ctx.accounts.token_lottery.record_ticket_opened()?;
+
+        Ok(())
+    }
+
+    pub fn claim_core_prize(ctx: Context<ClaimCorePrize>, ticket_id: u64) -> Result<()> {
+        require!(
+            ctx.accounts.token_lottery.ticket_mode == TicketMode::Core,
+            ErrorCode::InvalidTicketMode
+        );
+        // Check if the ticket won a prize tier that has not been paid yet
+        let tier = ctx.accounts.token_lottery.claimable_tier(ticket_id)?;
+
+        // Check if the winner owns the Core asset recorded at purchase
+        ctx.accounts.ticket_record.validate(&ctx.accounts.token_lottery.key(), ticket_id, &ctx.accounts.ticket_asset.key())?;
+        require!(
+            core_ticket_owner(&ctx.accounts.ticket_asset)? == ctx.accounts.payer.key(),
+            ErrorCode::IncorrectTicket
+        );
+
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
+
+        pay_prize(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.payer.to_account_info(),
+            payment,
+            ticket_id,
+            tier,
+        )?;
+
+        // 当選チケットを設定どおりにバーンまたは凍結する
+        let token_lottery_key = ctx.accounts.token_lottery.key();
+        retire_winning_ticket(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.ticket_record,
+            ctx.accounts.payer.to_account_info(),
+            || burn_core_ticket(
+                &ctx.accounts.mpl_core_program,
+                &ctx.accounts.ticket_asset,
+                &ctx.accounts.core_collection,
+                &ctx.accounts.payer,
+                &ctx.accounts.system_program,
+            ),
+            || {
+                // 所有者が凍結プラグインを追加する（解凍できるのは抽選PDAだけ）
+                AddPluginV1Cpi::new(
+                    &ctx.accounts.mpl_core_program,
+                    AddPluginV1CpiAccounts {
+                        asset: &ctx.accounts.ticket_asset,
+                        collection: Some(&ctx.accounts.core_collection),
+                        payer: &ctx.accounts.payer,
+                        authority: Some(&ctx.accounts.payer),
+                        system_program: &ctx.accounts.system_program,
+                        log_wrapper: None,
+                    },
+                    AddPluginV1InstructionArgs {
+                        plugin: Plugin::FreezeDelegate(FreezeDelegate { frozen: true }),
+                        init_authority: Some(PluginAuthority::Address {
+                            address: token_lottery_key,
+                        }),
+                    },
+                ).invoke()?;
+                Ok(())
+            },
+        )
+    }
+
+    pub fn refund_core_ticket(ctx: Context<RefundCoreTicket>, ticket_id: u64) -> Result<()> {
+        require!(ctx.accounts.token_lottery.cancelled, ErrorCode::LotteryNotCancelled);
+        require!(
+            ctx.accounts.token_lottery.ticket_mode == TicketMode::Core,
+            ErrorCode::InvalidTicketMode
+        );
+        ctx.accounts.ticket_record.validate(&ctx.accounts.token_lottery.key(), ticket_id, &ctx.accounts.ticket_asset.key())?;
+        require!(
+            core_ticket_owner(&ctx.accounts.ticket_asset)? == ctx.accounts.payer.key(),
+            ErrorCode::IncorrectTicket
+        );
+
+        // 1. チケットアセットをバーン（二重の払い戻しを防ぐ）
+        burn_core_ticket(
+            &ctx.accounts.mpl_core_program,
+            &ctx.accounts.ticket_asset,
+            &ctx.accounts.core_collection,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+        )?;
+
+        // 2. チケット代金を払い戻す（手数料分も返金する）
+        let payment = token_payment(
+            &ctx.accounts.token_lottery,
+            &ctx.accounts.payment_mint,
+            &ctx.accounts.vault,
+            &ctx.accounts.payer_token_account,
+            &ctx.accounts.payment_token_program,
+        )?;
+
+        refund_from_pot(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.payer.to_account_info(),
+            payment,
+            ticket_id,
+            1,
+        )?;
+        ctx.accounts.token_lottery.record_ticket_closed()?;
+
+        msg!("Refunded Core ticket {}", ticket_id);
+
+        Ok(())
+    }
+
+    pub fn close_core_ticket(ctx: Context<CloseCoreTicket>, ticket_id: u64) -> Result<()> {
+        let token_lottery = &ctx.accounts.token_lottery;
+        require!(token_lottery.ticket_mode == TicketMode::Core, ErrorCode::InvalidTicketMode);
+        require!(token_lottery.winner_chosen || token_lottery.cancelled, ErrorCode::WinnerNotChosen);
+        ctx.accounts.ticket_record.validate(&token_lottery.key(), ticket_id, &ctx.accounts.ticket_asset.key())?;
+        require!(
+            core_ticket_owner(&ctx.accounts.ticket_asset)? == ctx.accounts.payer.key(),
+            ErrorCode::IncorrectTicket
+        );
+
+        // 当選チケットは賞金を受け取るまで閉じられない
+        if let Some(tier) = token_lottery.winning_tier(ticket_id) {
+            require!(token_lottery.is_tier_claimed(tier), ErrorCode::UnclaimedPrize);
+        }
+
+        // チケットアセットをバーンしてレントを回収する
+        burn_core_ticket(
+            &ctx.accounts.mpl_core_program,
+            &ctx.accounts.ticket_asset,
+            &ctx.accounts.core_collection,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+        )?;
+
+        // キャンセルされた抽選のチケットは、閉じる前にチケット代金を払い戻す
+        if ctx.accounts.token_lottery.cancelled {
+            let payment = token_payment(
+                &ctx.accounts.token_lottery,
+                &ctx.accounts.payment_mint,
+                &ctx.accounts.vault,
+                &ctx.accounts.payer_token_account,
+                &ctx.accounts.payment_token_program,
+            )?;
+
+            refund_from_pot(
+                &mut ctx.accounts.token_lottery,
+                &ctx.accounts.payer.to_account_info(),
+                payment,
+                ticket_id,
+                1,
+            )?;
+        }
+
+        close_ticket_record(
+            &mut ctx.accounts.token_lottery,
+            &ctx.accounts.ticket_record,
+            ctx.accounts.payer.to_account_info(),
+        )?;
+
+        emit!(TicketClosed {
+            lottery: ctx.accounts.token_lottery.key(),
+            owner: ctx.accounts.payer.key(),
+            ticket_id,
+            slot: Clock::get()?.slot,
+        });
+
+        Ok(())
+    }
+}
+
+/// Checks that the prize tiers are non-empty, positive and split the whole pot.
+fn validate_prize_tiers(prize_tiers: &[u16]) -> Result<()> {
+    require!(
+        !prize_tiers.is_empty() &&
+        prize_tiers.len() <= MAX_PRIZE_TIERS as usize &&
+        prize_tiers.iter().all(|&bps| bps > 0) &&
+        prize_tiers.iter().map(|&bps| bps as u32).sum::<u32>() == TOTAL_PRIZE_BPS as u32,
+        ErrorCode::InvalidPrizeTiers
+    );
+    Ok(())
+}
+
+/// Draws `count` distinct ticket IDs out of `total_tickets`.
+/// Each draw picks uniformly among the tickets that have not won yet, so no ticket wins twice.
+fn draw_winners(randomness: &[u8; 32], total_tickets: u64, count: usize) -> Vec<u64> {
+    let mut winners: Vec<u64> = Vec::with_capacity(count);
+
+    for i in 0..count {
+        // 当選順ごとに全32バイトの乱数から独立したシードを導出する
+        let seed = hashv(&[randomness.as_ref(), (i as u64).to_le_bytes().as_ref()]).to_bytes();
+        let mut pick = uniform_index(&seed, total_tickets - i as u64);
+
+        // pick 番目の未当選チケットIDに変換する
+        let mut drawn = winners.clone();
+        drawn.sort_unstable();
+        for ticket_id in drawn {
+            if ticket_id <= pick {
+                pick += 1;
+            }
+        }
+
+        winners.push(pick);
+    }
+
+    winners
+}
+
+/// Maps a 32-byte seed to an index in `0..bound` without modulo bias.
+/// 128-bit words from the seed are rejection sampled against the largest multiple of `bound`,
+/// and the seed is rehashed in the (astronomically unlikely) case both words are rejected.
+fn uniform_index(seed: &[u8; 32], bound: u64) -> u64 {
+    let bound = bound as u128;
+    let zone = u128::MAX - (u128::MAX % bound + 1) % bound;
+    let mut seed = *seed;
+
+    loop {
+        for word in seed.chunks_exact(16) {
+            let value = u128::from_le_bytes(word.try_into().unwrap());
+            if value <= zone {
+                return (value % bound) as u64;
+            }
+        }
+        seed = hashv(&[seed.as_ref()]).to_bytes();
+    }
+}
+
+/// Reads the hash of the first block at or after `slot` from the SlotHashes sysvar.
+/// The sysvar is a length prefix followed by (slot, hash) entries, newest first, and only
+/// keeps the last 512 slots, so the hash must be read before `slot` ages out.
+fn slot_hash_at(slot_hashes: &AccountInfo, slot: u64) -> Result<[u8; 32]> {
+    let data = slot_hashes.try_borrow_data()?;
+    let len = data.get(..8).ok_or(ErrorCode::InvalidRandomnessData)?;
+    let len = u64::from_le_bytes(len.try_into().unwrap());
+
+    let mut first_block = None;
+    let mut reached_older = false;
+    for entry in data[8..].chunks_exact(40).take(len as usize) {
+        let entry_slot = u64::from_le_bytes(entry[..8].try_into().unwrap());
+        if entry_slot < slot {
+            reached_older = true;
+            break;
+        }
+        first_block = Some((entry_slot, <[u8; 32]>::try_from(&entry[8..]).unwrap()));
+    }
+
+    match first_block {
+        Some((entry_slot, hash)) if reached_older || entry_slot == slot => Ok(hash),
+        // 保持されているエントリがすべて新しい場合、最初のブロックは既に押し出されている可能性がある
+        Some(_) => err!(ErrorCode::SlotHashExpired),
+        None => err!(ErrorCode::RandomnessNotResolved),
+    }
+}
+
+/// Token accounts used to move the pot when a lottery is priced in an SPL token.
+#[derive(Clone, Copy)]
+struct TokenPayment<'a, 'info> {
+    mint: &'a InterfaceAccount<'info, Mint>,
+    vault: &'a InterfaceAccount<'info, TokenAccount>,
+    token_account: &'a InterfaceAccount<'info, TokenAccount>,
+    token_program: &'a Interface<'info, TokenInterface>,
+}
+
+/// Collects the optional payment accounts of an instruction.
+/// Returns `None` for SOL lotteries, and requires every account for token lotteries.
+fn token_payment<'a, 'info>(
+    token_lottery: &TokenLottery,
+    mint: &'a Option<Box<InterfaceAccount<'info, Mint>>>,
+    vault: &'a Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    token_account: &'a Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    token_program: &'a Option<Interface<'info, TokenInterface>>,
+) -> Result<Option<TokenPayment<'a, 'info>>> {
+    if !token_lottery.is_token_denominated() {
+        return Ok(None);
+    }
+
+    match (mint, vault, token_account, token_program) {
+        (Some(mint), Some(vault), Some(token_account), Some(token_program)) => {
+            require_keys_eq!(mint.key(), token_lottery.payment_mint, ErrorCode::IncorrectPaymentMint);
+            Ok(Some(TokenPayment {
+                mint,
+                vault,
+                token_account,
+                token_program,
+            }))
+        }
+        _ => err!(ErrorCode::MissingPaymentAccounts),
+    }
+}
+
+/// Rejects Token-2022 payment mints with extensions other than metadata. Transfer fees,
+/// hooks and the like would leave the vault holding less than the pot is credited with.
+fn validate_payment_mint(payment_mint: &AccountInfo) -> Result<()> {
+    if *payment_mint.owner != Token2022::id() {
+        return Ok(());
+    }
+
+    let data = payment_mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<MintState>::unpack(&data)?;
+    let metadata_only = mint.get_extension_types()?.iter().all(|extension| matches!(
+        extension,
+        ExtensionType::MetadataPointer | ExtensionType::TokenMetadata
+    ));
+    require!(metadata_only, ErrorCode::UnsupportedPaymentMint);
+    Ok(())
+}
+
+/// Moves `amount` from the payer into the lottery pot.
+fn deposit_to_pot<'info>(
+    token_lottery: &Account<'info, TokenLottery>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    payment: Option<TokenPayment<'_, 'info>>,
+    amount: u64,
+) -> Result<()> {
+    match payment {
+        Some(payment) => {
+            let transfer_accounts = TransferChecked {
+                from: payment.token_account.to_account_info(),
+                mint: payment.mint.to_account_info(),
+                to: payment.vault.to_account_info(),
+                authority: payer.to_account_info(),
+            };
+
+            let transfer_cpi_context = CpiContext::new(
+                payment.token_program.to_account_info(),
+                transfer_accounts,
+            );
+
+            transfer_checked(transfer_cpi_context, amount, payment.mint.decimals)
+        }
+        None => {
+            let transfer_accounts = Transfer {
+                from: payer.to_account_info(),
+                to: token_lottery.to_account_info(),
+            };
+
+            let transfer_cpi_context = CpiContext::new(
+                system_program.to_account_info(),
+                transfer_accounts,
+            );
+
+            transfer(transfer_cpi_context, amount)
+        }
+    }
+}
+
+/// Pays `amount` out of the lottery pot to `recipient`.
+/// SOL is moved directly since the lottery PDA carries data and cannot be debited by the system program.
+fn withdraw_from_pot<'info>(
+    token_lottery: &Account<'info, TokenLottery>,
+    recipient: &AccountInfo<'info>,
+    payment: Option<TokenPayment<'_, 'info>>,
+    amount: u64,
+) -> Result<()> {
+    match payment {
+        Some(payment) => {
+            let creator = token_lottery.creator;
+            let lottery_id = token_lottery.lottery_id.to_le_bytes();
+            let signer_seeds: &[&[&[u8]]] = &[&[
+                b"token_lottery".as_ref(),
+                creator.as_ref(),
+                lottery_id.as_ref(),
+                &[token_lottery.bump],
+            ]];
+
+            let transfer_accounts = TransferChecked {
+                from: payment.vault.to_account_info(),
+                mint: payment.mint.to_account_info(),
+                to: payment.token_account.to_account_info(),
+                authority: token_lottery.to_account_info(),
+            };
+
+            let transfer_cpi_context = CpiContext::new_with_signer(
+                payment.token_program.to_account_info(),
+                transfer_accounts,
+                signer_seeds,
+            );
+
+            transfer_checked(transfer_cpi_context, amount, payment.mint.decimals)
+        }
+        None => {
+            token_lottery.sub_lamports(amount)?;
+            recipient.add_lamports(amount)?;
+            Ok(())
+        }
+    }
+}
+
+/// Pays the prize of `tier` won by `ticket_id` to `winner` and marks the tier claimed.
+/// Shared by the claim instructions of every ticket mode.
+fn pay_prize<'info>(
+    token_lottery: &mut Account<'info, TokenLottery>,
+    winner: &AccountInfo<'info>,
+    payment: Option<TokenPayment<'_, 'info>>,
+    ticket_id: u64,
+    tier: usize,
+) -> Result<()> {
+    let prize = token_lottery.tier_prize(tier);
+    msg!("Prize tier: {}, amount: {}", tier, prize);
+
+    withdraw_from_pot(token_lottery, winner, payment, prize)?;
+
+    token_lottery.mark_tier_claimed(tier);
+
+    emit!(PrizeClaimed {
+        lottery: token_lottery.key(),
+        winner: winner.key(),
+        ticket_id,
+        tier: tier as u8,
+        amount: prize,
+        slot: Clock::get()?.slot,
+    });
+
+    Ok(())
+}
+
+/// Refunds `count` tickets from `first_ticket_id` on to `buyer` at full price, fee included.
+/// Shared by the refund instructions of every ticket mode.
+fn refund_from_pot<'info>(
+    token_lottery: &mut Account<'info, TokenLottery>,
+    buyer: &AccountInfo<'info>,
+    payment: Option<TokenPayment<'_, 'info>>,
+    first_ticket_id: u64,
+    count: u64,
+) -> Result<()> {
+    let (amount, _) = token_lottery.sale_amounts(count)?;
+    withdraw_from_pot(token_lottery, buyer, payment, amount)?;
+
+    token_lottery.record_refunds(count)?;
+
+    emit!(TicketsRefunded {
+        lottery: token_lottery.key(),
+        buyer: buyer.key(),
+        first_ticket_id,
+        count,
+        amount,
+        slot: Clock::get()?.slot,
+    });
+
+    Ok(())
+}
+
+/// Retires a claimed winning ticket according to `winning_ticket_action`.
+/// A burned or frozen ticket cannot be closed later, so its record is closed here.
+fn retire_winning_ticket<'info>(
+    token_lottery: &mut Account<'info, TokenLottery>,
+    ticket_record: &Account<'info, TicketRecord>,
+    receiver: AccountInfo<'info>,
+    burn: impl FnOnce() -> Result<()>,
+    freeze: impl FnOnce() -> Result<()>,
+) -> Result<()> {
+    match token_lottery.winning_ticket_action {
+        WinningTicketAction::Keep => return Ok(()),
+        WinningTicketAction::Burn => burn()?,
+        WinningTicketAction::Freeze => freeze()?,
+    }
+    close_ticket_record(token_lottery, ticket_record, receiver)
+}
+
+/// Closes the record of a retired ticket, so it no longer blocks `close_lottery`.
+fn close_ticket_record<'info>(
+    token_lottery: &mut Account<'info, TokenLottery>,
+    ticket_record: &Account<'info, TicketRecord>,
+    receiver: AccountInfo<'info>,
+) -> Result<()> {
+    ticket_record.close(receiver)?;
+    token_lottery.record_ticket_closed()
+}
+
+/// Burns a Token-2022 ticket and closes the holder's token account and the ticket mint,
+/// returning their rent to the holder. `signer_seeds` are the collection mint's seeds.
+fn burn_token2022_ticket<'info>(
+    token_program: &Program<'info, Token2022>,
+    ticket_mint: &InterfaceAccount<'info, Mint>,
+    token_account: &InterfaceAccount<'info, TokenAccount>,
+    owner: &Signer<'info>,
+    collection_mint: &InterfaceAccount<'info, Mint>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let burn_accounts = Burn {
+        mint: ticket_mint.to_account_info(),
+        from: token_account.to_account_info(),
+        authority: owner.to_account_info(),
+    };
+
+    burn(CpiContext::new(token_program.to_account_info(), burn_accounts), 1)?;
+
+    let close_accounts = CloseAccount {
+        account: token_account.to_account_info(),
+        destination: owner.to_account_info(),
+        authority: owner.to_account_info(),
+    };
+
+    close_account(CpiContext::new(token_program.to_account_info(), close_accounts))?;
+
+    // 供給量が0になったミントはcollection_mintがクローズ権限として閉じる
+    let close_mint_accounts = CloseAccount {
+        account: ticket_mint.to_account_info(),
+        destination: owner.to_account_info(),
+        authority: collection_mint.to_account_info(),
+    };
+
+    close_account(
+        CpiContext::new(token_program.to_account_info(), close_mint_accounts).with_signer(signer_seeds),
+    )
+}
+
+/// Burns a Core ticket asset on behalf of its owner.
+fn burn_core_ticket<'info>(
+    mpl_core_program: &AccountInfo<'info>,
+    ticket_asset: &AccountInfo<'info>,
+    core_collection: &AccountInfo<'info>,
+    owner: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    BurnV1Cpi::new(
+        mpl_core_program,
+        BurnV1CpiAccounts {
+            asset: ticket_asset,
+            collection: Some(core_collection),
+            payer: owner,
+            authority: Some(owner),
+            system_program: Some(system_program),
+            log_wrapper: None,
+        },
+        BurnV1InstructionArgs { compression_proof: None },
+    ).invoke()?;
+    Ok(())
+}
+
+/// Owner of the Core ticket asset. Burned assets no longer deserialize as `AssetV1`.
+fn core_ticket_owner(ticket_asset: &AccountInfo) -> Result<Pubkey> {
+    let asset = BaseAssetV1::from_bytes(&ticket_asset.try_borrow_data()?)
+        .map_err(|_| ErrorCode::IncorrectTicket)?;
+    require!(asset.key == mpl_core::types::Key::AssetV1, ErrorCode::IncorrectTicket);
+    Ok(asset.owner)
+}
+
+/// Tops up `mint` so it stays rent exempt once Token-2022 reallocates it to hold
+/// `metadata` next to the fixed-size `extensions`.
+fn fund_mint_extensions<'info>(
+    payer: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    extensions: &[ExtensionType],
+    metadata: &TokenMetadata,
+) -> Result<()> {
+    let len = ExtensionType::try_calculate_account_len::<MintState>(extensions)? + metadata.tlv_size_of()?;
+    let shortfall = Rent::get()?.minimum_balance(len).saturating_sub(mint.lamports());
+    if shortfall > 0 {
+        let transfer_accounts = Transfer {
+            from: payer.clone(),
+            to: mint.clone(),
+        };
+        transfer(CpiContext::new(system_program.clone(), transfer_accounts), shortfall)?;
+    }
+    Ok(())
+}
+
+/// Bubblegum metadata of compressed ticket `ticket_id`. Bubblegum verifies the collection
+/// while minting, so the stored leaf hashes the `verified: true` form.
+fn compressed_ticket_metadata(
+    metadata: &LotteryMetadata,
+    collection_mint: Pubkey,
+    ticket_id: u64,
+    verified: bool,
+) -> Result<MetadataArgs> {
+    Ok(MetadataArgs {
+        name: metadata.ticket_name(ticket_id)?,
+        symbol: metadata.symbol.clone(),
+        uri: metadata.ticket_uri(ticket_id)?,
+        seller_fee_basis_points: 0,
+        primary_sale_happened: false,
+        is_mutable: true,
+        edition_nonce: None,
+        token_standard: Some(TokenStandard::NonFungible),
+        collection: Some(mpl_bubblegum::types::Collection {
+            verified,
+            key: collection_mint,
+        }),
+        uses: None,
+        token_program_version: TokenProgramVersion::Original,
+        creators: vec![],
+    })
+}
+
+fn compressed_ticket_data_hash(metadata: &LotteryMetadata, collection_mint: Pubkey, ticket_id: u64) -> Result<[u8; 32]> {
+    let metadata = compressed_ticket_metadata(metadata, collection_mint, ticket_id, true)?;
+    Ok(hash_metadata(&metadata)?)
+}
+
+/// Leaf hash of compressed ticket `ticket_id`, minted at `nonce` in `merkle_tree`, while held by `owner`.
+fn compressed_ticket_leaf(
+    metadata: &LotteryMetadata,
+    collection_mint: Pubkey,
+    ticket_id: u64,
+    merkle_tree: &Pubkey,
+    nonce: u64,
+    owner: Pubkey,
+    delegate: Pubkey,
+) -> Result<[u8; 32]> {
+    let leaf = LeafSchema::V1 {
+        id: get_asset_id(merkle_tree, nonce),
+        owner,
+        delegate,
+        nonce,
+        data_hash: compressed_ticket_data_hash(metadata, collection_mint, ticket_id)?,
+        creator_hash: hash_creators(&[]),
+    };
+    Ok(leaf.hash())
+}
+
+/// Bubblegum appends every mint, so a leaf's index in the tree is its nonce.
+fn leaf_index(nonce: u64) -> Result<u32> {
+    u32::try_from(nonce).map_err(|_| ErrorCode::IncorrectTicket.into())
+}
+
+/// Fails unless `leaf` is at `index` in `merkle_tree` under `root`, with the proof
+/// nodes passed in order as `proof`.
+fn verify_ticket_leaf<'info>(
+    compression_program: &AccountInfo<'info>,
+    merkle_tree: &AccountInfo<'info>,
+    proof: &[AccountInfo<'info>],
+    root: [u8; 32],
+    leaf: [u8; 32],
+    index: u32,
+) -> Result<()> {
+    let mut accounts = vec![AccountMeta::new_readonly(merkle_tree.key(), false)];
+    accounts.extend(proof.iter().map(|node| AccountMeta::new_readonly(node.key(), false)));
+
+    let mut data = VERIFY_LEAF_DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&root);
+    data.extend_from_slice(&leaf);
+    data.extend_from_slice(&index.to_le_bytes());
+
+    let mut account_infos = vec![merkle_tree.clone()];
+    account_infos.extend(proof.iter().cloned());
+
+    invoke(
+        &Instruction {
+            program_id: compression_program.key(),
+            accounts,
+            data,
+        },
+        &account_infos,
+    )?;
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(ticket_id: u64)]
+pub struct ClaimPrize<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        mut,
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_MINT_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub ticket_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_RECORD_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump = ticket_record.bump,
+    )]
+    pub ticket_record: Account<'info, TicketRecord>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), ticket_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(),
+            ticket_mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    /// CHECK: The ticket's freeze authority, closed by the token metadata program when burned
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = ticket_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = token_program,
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), collection_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    pub collection_metadata: Account<'info, MetadataAccount>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+    pub token_metadata_program: Program<'info, Metadata>,
+}
+
+#[derive(Accounts)]
+#[instruction(ticket_id: u64)]
+pub struct MarkWinner<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        seeds = [TICKET_MINT_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub ticket_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        seeds = [TICKET_RECORD_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump = ticket_record.bump,
+    )]
+    pub ticket_record: Account<'info, TicketRecord>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), ticket_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    pub metadata: Account<'info, MetadataAccount>,
+
+    pub token_metadata_program: Program<'info, Metadata>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    /// CHECK: Only receives fees; checked against the stored treasury.
+    #[account(
+        mut,
+        address = token_lottery.treasury,
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = treasury,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub treasury_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseLottery<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        close = payer,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"collection_token_account".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    /// CHECK: Checked against collection_mint and closed by the token metadata program
+    pub collection_metadata: Option<UncheckedAccount<'info>>,
+
+    #[account(mut)]
+    /// CHECK: Checked against collection_mint and closed by the token metadata program
+    pub collection_master_edition: Option<UncheckedAccount<'info>>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+    pub token_metadata_program: Option<Program<'info, Metadata>>,
+}
+
+#[derive(Accounts)]
+#[instruction(ticket_id: u64)]
+pub struct CloseTicket<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_MINT_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub ticket_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        close = payer,
+        seeds = [TICKET_RECORD_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump = ticket_record.bump,
+    )]
+    pub ticket_record: Account<'info, TicketRecord>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), ticket_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    /// CHECK: Closed by the token metadata program when the ticket is burned
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(),
+            ticket_mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    /// CHECK: Closed by the token metadata program when the ticket is burned
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = ticket_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = token_program,
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), collection_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    /// CHECK: The collection's size is decremented by the token metadata program
+    pub collection_metadata: UncheckedAccount<'info>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+    pub token_metadata_program: Program<'info, Metadata>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTicketTree<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        mut,
+        seeds = [merkle_tree.key().as_ref()],
+        bump,
+        seeds::program = bubblegum_program.key(),
+    )]
+    /// CHECK: Initialized and checked by the Bubblegum program
+    pub tree_config: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    /// CHECK: Empty concurrent merkle tree allocated by the client, validated by the compression program
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    #[account(address = SPL_NOOP_ID)]
+    /// CHECK: SPL noop program, checked by address
+    pub log_wrapper: UncheckedAccount<'info>,
+
+    #[account(address = SPL_ACCOUNT_COMPRESSION_ID)]
+    /// CHECK: SPL account compression program, checked by address
+    pub compression_program: UncheckedAccount<'info>,
+
+    #[account(address = mpl_bubblegum::ID)]
+    /// CHECK: Bubblegum program, checked by address
+    pub bubblegum_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyCompressedTicket<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Box<Account<'info, TokenLottery>>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + BuyerState::INIT_SPACE,
+        seeds = [BUYER_STATE_SEED, token_lottery.key().as_ref(), payer.key().as_ref()],
+        bump,
+    )]
+    pub buyer_state: Box<Account<'info, BuyerState>>,
+
+    #[account(
+        mut,
+        seeds = [merkle_tree.key().as_ref()],
+        bump,
+        seeds::program = bubblegum_program.key(),
+    )]
+    /// CHECK: Initialized and checked by the Bubblegum program
+    pub tree_config: UncheckedAccount<'info>,
+
+    #[account(mut, address = token_lottery.merkle_tree)]
+    /// CHECK: Concurrent merkle tree owned by the account compression program
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), collection_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    /// CHECK: Collection metadata, checked by the metaplex program
+    pub collection_metadata: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"metadata", token_metadata_program.key().as_ref(),
+            collection_mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    /// CHECK: Collection master edition, checked by the metaplex program
+    pub collection_master_edition: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"collection_cpi".as_ref()],
+        bump,
+        seeds::program = bubblegum_program.key(),
+    )]
+    /// CHECK: Bubblegum PDA that signs collection size updates
+    pub bubblegum_signer: UncheckedAccount<'info>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(address = SPL_NOOP_ID)]
+    /// CHECK: SPL noop program, checked by address
+    pub log_wrapper: UncheckedAccount<'info>,
+
+    #[account(address = SPL_ACCOUNT_COMPRESSION_ID)]
+    /// CHECK: SPL account compression program, checked by address
+    pub compression_program: UncheckedAccount<'info>,
+
+    #[account(address = mpl_bubblegum::ID)]
+    /// CHECK: Bubblegum program, checked by address
+    pub bubblegum_program: UncheckedAccount<'info>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+    pub token_metadata_program: Program<'info, Metadata>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimCompressedPrize<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: Delegate of the winning leaf, only hashed into the leaf
+    pub leaf_delegate: UncheckedAccount<'info>,
+
+    #[account(address = token_lottery.merkle_tree)]
+    /// CHECK: Concurrent merkle tree owned by the account compression program
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    #[account(address = SPL_ACCOUNT_COMPRESSION_ID)]
+    /// CHECK: SPL account compression program, checked by address
+    pub compression_program: UncheckedAccount<'info>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefundCompressedTicket<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: Delegate of the refunded leaf, checked by the Bubblegum program
+    pub leaf_delegate: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [merkle_tree.key().as_ref()],
+        bump,
+        seeds::program = bubblegum_program.key(),
+    )]
+    /// CHECK: Initialized and checked by the Bubblegum program
+    pub tree_config: UncheckedAccount<'info>,
+
+    #[account(mut, address = token_lottery.merkle_tree)]
+    /// CHECK: Concurrent merkle tree owned by the account compression program
+    pub merkle_tree: UncheckedAccount<'info>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(address = SPL_NOOP_ID)]
+    /// CHECK: SPL noop program, checked by address
+    pub log_wrapper: UncheckedAccount<'info>,
+
+    #[account(address = SPL_ACCOUNT_COMPRESSION_ID)]
+    /// CHECK: SPL account compression program, checked by address
+    pub compression_program: UncheckedAccount<'info>,
+
+    #[account(address = mpl_bubblegum::ID)]
+    /// CHECK: Bubblegum program, checked by address
+    pub bubblegum_program: UncheckedAccount<'info>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTokenGroup<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        init,
+        payer = payer,
+        mint::decimals = 0,
+        mint::authority = collection_mint,
+        mint::freeze_authority = collection_mint,
+        mint::token_program = token_program,
+        extensions::metadata_pointer::authority = collection_mint,
+        extensions::metadata_pointer::metadata_address = collection_mint,
+        extensions::group_pointer::authority = collection_mint,
+        extensions::group_pointer::group_address = collection_mint,
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    pub token_program: Program<'info, Token2022>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyToken2022Ticket<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Box<Account<'info, TokenLottery>>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [TICKET_MINT_SEED, token_lottery.key().as_ref(), token_lottery.total_tickets.to_le_bytes().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = collection_mint,
+        mint::freeze_authority = collection_mint,
+        mint::token_program = token_program,
+        extensions::metadata_pointer::authority = collection_mint,
+        extensions::metadata_pointer::metadata_address = ticket_mint,
+        extensions::group_member_pointer::authority = collection_mint,
+        extensions::group_member_pointer::member_address = ticket_mint,
+        extensions::close_authority::authority = collection_mint,
+    )]
+    pub ticket_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + TicketRecord::INIT_SPACE,
+        seeds = [TICKET_RECORD_SEED, token_lottery.key().as_ref(), token_lottery.total_tickets.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub ticket_record: Box<Account<'info, TicketRecord>>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + BuyerState::INIT_SPACE,
+        seeds = [BUYER_STATE_SEED, token_lottery.key().as_ref(), payer.key().as_ref()],
+        bump,
+    )]
+    pub buyer_state: Box<Account<'info, BuyerState>>,
+
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = ticket_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = token_program,
+    )]
+    pub destination: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token2022>,
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(ticket_id: u64)]
+pub struct ClaimToken2022Prize<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_MINT_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub ticket_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_RECORD_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump = ticket_record.bump,
+    )]
+    pub ticket_record: Account<'info, TicketRecord>,
+
+    #[account(
+        mut,
+        associated_token::mint = ticket_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = token_program,
+    )]
+    pub destination: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Program<'info, Token2022>,
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(ticket_id: u64)]
+pub struct RefundToken2022Ticket<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_MINT_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub ticket_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        close = payer,
+        seeds = [TICKET_RECORD_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump = ticket_record.bump,
+    )]
+    pub ticket_record: Account<'info, TicketRecord>,
+
+    #[account(
+        mut,
+        associated_token::mint = ticket_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = token_program,
+    )]
+    pub destination: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Program<'info, Token2022>,
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(ticket_id: u64)]
+pub struct CloseToken2022Ticket<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_MINT_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub ticket_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_RECORD_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump = ticket_record.bump,
+    )]
+    pub ticket_record: Account<'info, TicketRecord>,
+
+    #[account(
+        mut,
+        associated_token::mint = ticket_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = token_program,
+    )]
+    pub destination: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Program<'info, Token2022>,
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyCoreTicket<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Box<Account<'info, TokenLottery>>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_MINT_SEED, token_lottery.key().as_ref(), token_lottery.total_tickets.to_le_bytes().as_ref()],
+        bump,
+    )]
+    /// CHECK: Created by the mpl-core program as the ticket asset
+    pub ticket_asset: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + TicketRecord::INIT_SPACE,
+        seeds = [TICKET_RECORD_SEED, token_lottery.key().as_ref(), token_lottery.total_tickets.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub ticket_record: Box<Account<'info, TicketRecord>>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + BuyerState::INIT_SPACE,
+        seeds = [BUYER_STATE_SEED, token_lottery.key().as_ref(), payer.key().as_ref()],
+        bump,
+    )]
+    pub buyer_state: Box<Account<'info, BuyerState>>,
+
+    #[account(
+        mut,
+        seeds = [CORE_COLLECTION_SEED, token_lottery.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: Core collection created by `initialize_lottery`, checked by the mpl-core program
+    pub core_collection: UncheckedAccount<'info>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(address = mpl_core::ID)]
+    /// CHECK: mpl-core program, checked by address
+    pub mpl_core_program: UncheckedAccount<'info>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(ticket_id: u64)]
+pub struct ClaimCorePrize<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_MINT_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump,
+        owner = mpl_core::ID,
+    )]
+    /// CHECK: Core ticket asset, deserialized in the instruction
+    pub ticket_asset: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_RECORD_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump = ticket_record.bump,
+    )]
+    pub ticket_record: Account<'info, TicketRecord>,
+
+    #[account(
+        mut,
+        seeds = [CORE_COLLECTION_SEED, token_lottery.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: Core collection created by `initialize_lottery`, checked by the mpl-core program
+    pub core_collection: UncheckedAccount<'info>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(address = mpl_core::ID)]
+    /// CHECK: mpl-core program, checked by address
+    pub mpl_core_program: UncheckedAccount<'info>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(ticket_id: u64)]
+pub struct RefundCoreTicket<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_MINT_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump,
+        owner = mpl_core::ID,
+    )]
+    /// CHECK: Core ticket asset, deserialized in the instruction
+    pub ticket_asset: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        close = payer,
+        seeds = [TICKET_RECORD_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump = ticket_record.bump,
+    )]
+    pub ticket_record: Account<'info, TicketRecord>,
+
+    #[account(
+        mut,
+        seeds = [CORE_COLLECTION_SEED, token_lottery.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: Core collection created by `initialize_lottery`, checked by the mpl-core program
+    pub core_collection: UncheckedAccount<'info>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(address = mpl_core::ID)]
+    /// CHECK: mpl-core program, checked by address
+    pub mpl_core_program: UncheckedAccount<'info>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(ticket_id: u64)]
+pub struct CloseCoreTicket<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_MINT_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump,
+        owner = mpl_core::ID,
+    )]
+    /// CHECK: Core ticket asset, deserialized in the instruction
+    pub ticket_asset: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_RECORD_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump = ticket_record.bump,
+    )]
+    pub ticket_record: Account<'info, TicketRecord>,
+
+    #[account(
+        mut,
+        seeds = [CORE_COLLECTION_SEED, token_lottery.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: Core collection created by `initialize_lottery`, checked by the mpl-core program
+    pub core_collection: UncheckedAccount<'info>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(address = mpl_core::ID)]
+    /// CHECK: mpl-core program, checked by address
+    pub mpl_core_program: UncheckedAccount<'info>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelLottery<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+}
+
+#[derive(Accounts)]
+#[instruction(ticket_id: u64)]
+pub struct RefundTicket<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_MINT_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub ticket_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        close = payer,
+        seeds = [TICKET_RECORD_SEED, token_lottery.key().as_ref(), ticket_id.to_le_bytes().as_ref()],
+        bump = ticket_record.bump,
+    )]
+    pub ticket_record: Account<'info, TicketRecord>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), ticket_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    /// CHECK: Closed by the token metadata program when the ticket is burned
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(),
+            ticket_mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    /// CHECK: Closed by the token metadata program when the ticket is burned
+    pub master_edition: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = ticket_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = token_program,
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), collection_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    /// CHECK: The collection's size is decremented by the token metadata program
+    pub collection_metadata: UncheckedAccount<'info>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+    pub token_metadata_program: Program<'info, Metadata>,
+}
+
+#[derive(Accounts)]
+pub struct BuyTickets<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + TicketReceipt::INIT_SPACE,
+        seeds = [TICKET_RECEIPT_SEED, token_lottery.key().as_ref(), token_lottery.total_tickets.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub receipt: Account<'info, TicketReceipt>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + BuyerState::INIT_SPACE,
+        seeds = [BUYER_STATE_SEED, token_lottery.key().as_ref(), payer.key().as_ref()],
+        bump,
+    )]
+    pub buyer_state: Box<Account<'info, BuyerState>>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(first_ticket: u64)]
+pub struct ClaimReceiptPrize<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        mut,
+        seeds = [TICKET_RECEIPT_SEED, token_lottery.key().as_ref(), first_ticket.to_le_bytes().as_ref()],
+        bump = receipt.bump,
+    )]
+    pub receipt: Account<'info, TicketReceipt>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(first_ticket: u64)]
+pub struct RefundReceipt<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        mut,
+        close = payer,
+        seeds = [TICKET_RECEIPT_SEED, token_lottery.key().as_ref(), first_ticket.to_le_bytes().as_ref()],
+        bump = receipt.bump,
+    )]
+    pub receipt: Account<'info, TicketReceipt>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(first_ticket: u64)]
+pub struct CloseReceipt<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        mut,
+        close = payer,
+        seeds = [TICKET_RECEIPT_SEED, token_lottery.key().as_ref(), first_ticket.to_le_bytes().as_ref()],
+        bump = receipt.bump,
+    )]
+    pub receipt: Account<'info, TicketReceipt>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CommitWinner<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    /// CHECK: The account's data is validated manually within the handler.
+    pub randomness_account_data: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RecommitWinner<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    /// CHECK: The currently committed randomness account, checked to be still unrevealed.
+    #[account(address = token_lottery.randomness_account)]
+    pub previous_randomness_account: UncheckedAccount<'info>,
+
+    /// CHECK: The account's data is validated manually within the handler.
+    pub randomness_account_data: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevealWinner<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    /// CHECK: The account's data is validated manually within the handler.
+    /// Not needed for commit-reveal lotteries.
+    pub randomness_account_data: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: The SlotHashes sysvar, read manually since it is too large to deserialize.
+    #[account(address = slot_hashes::ID)]
+    pub recent_slothashes: UncheckedAccount<'info>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Receives the crank reward of a token-denominated lottery.
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyTicket<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump
+    )]
+    pub token_lottery: Account<'info, TokenLottery>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [TICKET_MINT_SEED, token_lottery.key().as_ref(), token_lottery.total_tickets.to_le_bytes().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = collection_mint,
+        mint::freeze_authority = collection_mint,
+        mint::token_program = token_program
+    )]
+    pub ticket_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + TicketRecord::INIT_SPACE,
+        seeds = [TICKET_RECORD_SEED, token_lottery.key().as_ref(), token_lottery.total_tickets.to_le_bytes().as_ref()],
+        bump,
+    )]
+    pub ticket_record: Account<'info, TicketRecord>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + BuyerState::INIT_SPACE,
+        seeds = [BUYER_STATE_SEED, token_lottery.key().as_ref(), payer.key().as_ref()],
+        bump,
+    )]
+    pub buyer_state: Box<Account<'info, BuyerState>>,
+
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = ticket_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = token_program,
+    )]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), 
+        ticket_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    /// CHECK: This account will be initialized by the metaplex program
+    pub metadata: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), 
+            ticket_mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
     /// CHECK: This account will be initialized by the metaplex program
     pub master_edition: UncheckedAccount<'info>,
 
-    #[account(
-        mut,
-        seeds = [b"metadata", token_metadata_program.key().as_ref(), collection_mint.key().as_ref()],
-        bump,
-        seeds::program = token_metadata_program.key(),
-    )]
-    /// CHECK: This account will be initialized by the metaplex program
-    pub collection_metadata: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), collection_mint.key().as_ref()],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    /// CHECK: This account will be initialized by the metaplex program
+    pub collection_metadata: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"metadata", token_metadata_program.key().as_ref(), 
+            collection_mint.key().as_ref(), b"edition"],
+        bump,
+        seeds::program = token_metadata_program.key(),
+    )]
+    /// CHECK: This account will be initialized by the metaplex program
+    pub collection_master_edition: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: InterfaceAccount<'info, Mint>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        mut,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = payer,
+        associated_token::token_program = payment_token_program,
+    )]
+    pub payer_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+/// The lottery PDA is seeded by the creating wallet and `lottery_id`, so each creator has its own
+/// ID space and nobody else can take an ID first.
+#[derive(Accounts)]
+#[instruction(lottery_id: u64)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + TokenLottery::INIT_SPACE,
+        seeds = [b"token_lottery".as_ref(), payer.key().as_ref(), lottery_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub token_lottery: Box<Account<'info, TokenLottery>>,
+
+    pub payment_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"vault".as_ref(), token_lottery.key().as_ref()],
+        bump,
+        token::mint = payment_mint,
+        token::authority = token_lottery,
+        token::token_program = payment_token_program,
+    )]
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub payment_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeLottery<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        seeds = [b"token_lottery".as_ref(), token_lottery.creator.as_ref(), token_lottery.lottery_id.to_le_bytes().as_ref()],
+        bump = token_lottery.bump,
+    )]
+    pub token_lottery: Box<Account<'info, TokenLottery>>,
+
+    // The SPL collection accounts are not needed for Core ticket lotteries
+    #[account(
+        init,
+        payer = payer,
+        mint::decimals = 0,
+        mint::authority = collection_mint,
+        mint::freeze_authority = collection_mint,
+        seeds = [b"collection_mint".as_ref(), token_lottery.key().as_ref()],
+        bump,
+    )]
+    pub collection_mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    /// CHECK: This account will be initialized by the metaplex program
+    #[account(mut)]
+    pub metadata: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: This account will be initialized by the metaplex program
+    #[account(mut)]
+    pub master_edition: Option<UncheckedAccount<'info>>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        seeds = [b"collection_token_account".as_ref(), token_lottery.key().as_ref()],
+        bump,
+        token::mint = collection_mint,
+        token::authority = collection_token_account
+    )]
+    pub collection_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub rent: Sysvar<'info, Rent>,
+
+    #[account(
+        mut,
+        seeds = [CORE_COLLECTION_SEED, token_lottery.key().as_ref()],
+        bump,
+    )]
+    /// CHECK: Created by the mpl-core program. Only needed for Core ticket lotteries
+    pub core_collection: Option<UncheckedAccount<'info>>,
+
+    #[account(address = mpl_core::ID)]
+    /// CHECK: mpl-core program, checked by address
+    pub mpl_core_program: Option<UncheckedAccount<'info>>,
+}
+
+#[account]
+#[derive(InitSpace, Default)]
+pub struct TokenLottery {
+    pub bump: u8,
+    pub lottery_id: u64,
+    /// Wallet that created the lottery. Part of the lottery PDA seeds, so unlike `authority`
+    /// it never changes.
+    pub creator: Pubkey,
+    #[max_len(MAX_PRIZE_TIERS)]
+    pub winning_ticket_ids: Vec<u64>,
+    pub winner_chosen: bool,
+    pub lottery_start: u64,
+    pub lottery_end: u64,
+    pub lottery_pot_amount: u64,
+    pub total_tickets: u64,
+    /// Ticket records that have not been closed yet; `close_lottery` waits for them.
+    pub open_tickets: u64,
+    pub price: u64,
+    pub randomness_account: Pubkey,
+    pub authority: Pubkey,
+    pub payment_mint: Pubkey,
+    pub fee_bps: u16,
+    pub treasury: Pubkey,
+    pub accrued_fees: u64,
+    #[max_len(MAX_PRIZE_TIERS)]
+    pub prize_tiers: Vec<u16>,
+    pub claimed_tiers: u16,
+    pub cancelled: bool,
+    pub cancel_timeout: u64,
+    pub prize_claimed: bool,
+    pub winning_ticket_action: WinningTicketAction,
+    pub max_tickets: Option<u64>,
+    pub max_tickets_per_wallet: Option<u64>,
+    pub randomness_provider: RandomnessProvider,
+    pub secret_commitment: [u8; 32],
+    pub entropy_accumulator: [u8; 32],
+    pub permissionless_draw: bool,
+    pub crank_reward: u64,
+    pub commit_slot: u64,
+    /// Slot after the commit; a commit-reveal draw mixes in the hash of the first block from it.
+    pub reveal_slot: u64,
+    pub recommit_timeout: u64,
+    /// Randomness accounts replaced by `recommit_winner`, oldest first.
+    #[max_len(MAX_RANDOMNESS_HISTORY)]
+    pub randomness_history: Vec<Pubkey>,
+    /// Set by `propose_authority` and cleared once the new authority accepts.
+    pub pending_authority: Option<Pubkey>,
+    pub paused: bool,
+    pub schedule_mode: ScheduleMode,
+    pub metadata: LotteryMetadata,
+    pub ticket_mode: TicketMode,
+    /// Bubblegum tree holding compressed tickets, set by `initialize_ticket_tree`.
+    pub merkle_tree: Pubkey,
+}
+
+impl TokenLottery {
+    /// Compressed tickets cannot be burned or frozen on claim, so they only support `Keep`.
+    pub fn validate_ticket_mode(&self) -> Result<()> {
+        require!(
+            self.ticket_mode != TicketMode::Compressed ||
+            self.winning_ticket_action == WinningTicketAction::Keep,
+            ErrorCode::InvalidTicketMode
+        );
+        Ok(())
+    }
+
+    /// Whether tickets are priced in an SPL token rather than SOL.
+    pub fn is_token_denominated(&self) -> bool {
+        self.payment_mint != Pubkey::default()
+    }
+
+    /// Checks that the sales window is not empty and has not ended yet.
+    pub fn validate_schedule(&self, clock: &Clock) -> Result<()> {
+        // スロット・タイムスタンプどちらの場合も、開始は終了より前でなければならない
+        require!(self.lottery_start < self.lottery_end, ErrorCode::InvalidSchedule);
+        require!(self.lottery_end > self.schedule_mode.now(clock), ErrorCode::InvalidSchedule);
+        Ok(())
+    }
+
+    pub fn sales_ended(&self, clock: &Clock) -> bool {
+        self.schedule_mode.now(clock) > self.lottery_end
+    }
+
+    /// Checks that the schedule, price and prize terms can still change. They lock once a
+    /// ticket is sold, sales close, randomness is committed, a winner is chosen or the
+    /// lottery is cancelled, so nobody can tune them after learning the draw.
+    pub fn check_sale_terms_unlocked(&self, clock: &Clock) -> Result<()> {
+        require!(self.total_tickets == 0, ErrorCode::ConfigLocked);
+        require!(!self.sales_ended(clock), ErrorCode::ConfigLocked);
+        require!(self.randomness_account == Pubkey::default(), ErrorCode::ConfigLocked);
+        require!(!self.winner_chosen && !self.cancelled, ErrorCode::ConfigLocked);
+        Ok(())
+    }
 
-    #[account(
-        mut,
-        seeds = [b"metadata", token_metadata_program.key().as_ref(), 
-            collection_mint.key().as_ref(), b"edition"],
-        bump,
-        seeds::program = token_metadata_program.key(),
-    )]
-    /// CHECK: This account will be initialized by the metaplex program
-    pub collection_master_edition: UncheckedAccount<'info>,
+    /// Protocol fee taken from each ticket sale.
+    pub fn ticket_fee(&self) -> u64 {
+        (self.price as u128 * self.fee_bps as u128 / MAX_FEE_BPS as u128) as u64
+    }
 
-    #[account(
-        mut,
-        seeds = [b"collection_mint".as_ref()],
-        bump,
-    )]
-    pub collection_mint: InterfaceAccount<'info, Mint>,
+    pub fn check_sales_open(&self, clock: &Clock) -> Result<()> {
+        let now = self.schedule_mode.now(clock);
+        require!(
+            now >= self.lottery_start &&
+            now <= self.lottery_end,
+            ErrorCode::LotteryNotOpen
+        );
+        require!(!self.cancelled, ErrorCode::LotteryCancelled);
+        require!(!self.paused, ErrorCode::LotteryPaused);
+        Ok(())
+    }
+
+    /// Mixes entropy contributed by a buyer into the accumulator used by commit-reveal draws.
+    pub fn mix_entropy(&mut self, buyer: &Pubkey, entropy: &[u8; 32]) {
+        self.entropy_accumulator = hashv(&[
+            self.entropy_accumulator.as_ref(),
+            buyer.as_ref(),
+            entropy.as_ref(),
+        ]).to_bytes();
+    }
+
+    /// Checks the authority's secret against its commitment and derives the draw randomness
+    /// from the secret, the buyers' accumulated entropy and a recent slot hash.
+    pub fn reveal_secret(&self, secret: &[u8; 32], recent_slot_hash: &[u8; 32]) -> Result<[u8; 32]> {
+        require!(
+            hashv(&[secret.as_ref()]).to_bytes() == self.secret_commitment,
+            ErrorCode::IncorrectSecret
+        );
+
+        Ok(hashv(&[
+            secret.as_ref(),
+            self.entropy_accumulator.as_ref(),
+            recent_slot_hash.as_ref(),
+        ]).to_bytes())
+    }
+
+    /// Slot whose hash a commit-reveal draw uses. The authority's secret is accepted with the
+    /// hash of `reveal_slot` until `COMMIT_REVEAL_DEADLINE_SLOTS` later; from then on the draw can
+    /// only be made without the secret, from the hash of that deadline slot. Withholding the
+    /// secret therefore cannot stall the lottery, and the fallback hash is unknown while the
+    /// secret could still be revealed.
+    pub fn commit_reveal_hash_slot(&self, with_secret: bool, slot: u64) -> Result<u64> {
+        require!(self.randomness_account != Pubkey::default(), ErrorCode::RandomnessNotCommitted);
+        let deadline = self.reveal_slot.saturating_add(COMMIT_REVEAL_DEADLINE_SLOTS);
+        if with_secret {
+            require!(slot < deadline, ErrorCode::RevealDeadlinePassed);
+            Ok(self.reveal_slot)
+        } else {
+            require!(slot > deadline, ErrorCode::RevealDeadlineNotReached);
+            Ok(deadline)
+        }
+    }
+
+    /// Randomness of a commit-reveal draw made without the secret after the reveal deadline.
+    pub fn fallback_randomness(&self, deadline_slot_hash: &[u8; 32]) -> [u8; 32] {
+        hashv(&[
+            self.secret_commitment.as_ref(),
+            self.entropy_accumulator.as_ref(),
+            deadline_slot_hash.as_ref(),
+        ]).to_bytes()
+    }
+
+    /// Draws the winning tickets from the revealed randomness.
+    /// When fewer tickets were sold than there are prize tiers, only that many winners are drawn.
+    pub fn record_draw(&mut self, randomness: &[u8; 32]) {
+        let winner_count = self.prize_tiers.len().min(self.total_tickets as usize);
+        self.winning_ticket_ids = draw_winners(randomness, self.total_tickets, winner_count);
+        self.winner_chosen = true;
+    }
+
+    /// Stores the randomness account the draw is committed to.
+    pub fn record_commit(&mut self, randomness_account: Pubkey, slot: u64) {
+        self.randomness_account = randomness_account;
+        self.commit_slot = slot;
+        self.reveal_slot = slot.saturating_add(1);
+    }
+
+    /// Checks that the committed randomness can be replaced by `randomness_account` at `slot`:
+    /// the provider must prove the oracle failed, the previous value must still be unrevealed,
+    /// `recommit_timeout` must have passed since the last commit, and no randomness account
+    /// may be committed twice.
+    pub fn check_can_recommit(&self, randomness_account: &Pubkey, previous_revealed: bool, slot: u64) -> Result<()> {
+        require!(!self.winner_chosen, ErrorCode::WinnerChosen);
+        require!(!self.cancelled, ErrorCode::LotteryCancelled);
+        require!(self.randomness_provider.allows_recommit(), ErrorCode::RecommitNotAllowed);
+        require!(self.randomness_account != Pubkey::default(), ErrorCode::RandomnessNotCommitted);
+
+        // 前回のコミットから recommit_timeout スロット経過するまで待つ
+        require!(
+            slot > self.commit_slot.saturating_add(self.recommit_timeout),
+            ErrorCode::RecommitTooEarly
+        );
+        require!(!previous_revealed, ErrorCode::RandomnessAlreadyRevealed);
+        require!(
+            *randomness_account != self.randomness_account &&
+            !self.randomness_history.contains(randomness_account),
+            ErrorCode::IncorrectRandomnessAccount
+        );
+        require!(
+            self.randomness_history.len() < MAX_RANDOMNESS_HISTORY as usize,
+            ErrorCode::TooManyRecommits
+        );
+        Ok(())
+    }
+
+    /// Seed an ORAO request must be made with to be committed. It covers the final ticket count
+    /// and buyer entropy, so requests made before ticket sales closed do not match, and the
+    /// commit attempt, so every recommit needs a request made for it.
+    pub fn orao_request_seed(&self) -> [u8; 32] {
+        let attempt = self.randomness_history.len() as u64 + u64::from(self.randomness_account != Pubkey::default());
+        hashv(&[
+            crate::ID.as_ref(),
+            self.creator.as_ref(),
+            self.lottery_id.to_le_bytes().as_ref(),
+            self.total_tickets.to_le_bytes().as_ref(),
+            self.entropy_accumulator.as_ref(),
+            attempt.to_le_bytes().as_ref(),
+        ]).to_bytes()
+    }
+
+    /// Checks that `account` may be committed to as the lottery's next randomness.
+    /// Commit-reveal lotteries commit to the SlotHashes sysvar, fixing `reveal_slot`; they
+    /// cannot be recommitted, so the authority never gets to pick another slot hash.
+    pub fn verify_commit(&self, account: &AccountInfo, clock: &Clock) -> Result<()> {
+        match self.randomness_provider {
+            RandomnessProvider::CommitReveal => {
+                require_keys_eq!(account.key(), slot_hashes::ID, ErrorCode::IncorrectRandomnessAccount);
+                return Ok(());
+            }
+            RandomnessProvider::Orao => {
+                require!(
+                    OraoRandomness::request_seed(account)? == self.orao_request_seed(),
+                    ErrorCode::IncorrectRandomnessAccount
+                );
+            }
+            RandomnessProvider::Switchboard { .. } => {}
+        }
+        self.randomness_provider.verify_commit(account, clock)
+    }
+
+    /// The authority may cancel until randomness is committed; after that, and for everyone
+    /// else, the lottery can only be cancelled once `cancel_timeout` has passed since the end.
+    pub fn check_can_cancel(&self, caller: &Pubkey, now: u64) -> Result<()> {
+        require!(!self.winner_chosen, ErrorCode::WinnerChosen);
+        require!(!self.cancelled, ErrorCode::LotteryCancelled);
+        let committed = self.randomness_account != Pubkey::default();
+        require!(
+            (*caller == self.authority && !committed) ||
+            now > self.lottery_end.saturating_add(self.cancel_timeout),
+            ErrorCode::NotAuthorized
+        );
+        Ok(())
+    }
+
+    /// Checks that `caller` may commit or reveal the draw.
+    pub fn check_can_draw(&self, caller: &Pubkey) -> Result<()> {
+        require!(
+            self.permissionless_draw || *caller == self.authority,
+            ErrorCode::NotAuthorized
+        );
+        Ok(())
+    }
+
+    /// Takes the crank reward out of the accrued fees, capped at what has accrued.
+    pub fn take_crank_reward(&mut self) -> u64 {
+        let reward = self.crank_reward.min(self.accrued_fees);
+        self.accrued_fees -= reward;
+        reward
+    }
+
+    /// Checks that selling `count` more tickets to a buyer who already holds `buyer_tickets`
+    /// stays within the lottery's supply and per-wallet caps.
+    pub fn check_ticket_limits(&self, buyer_tickets: u64, count: u64) -> Result<()> {
+        if let Some(max_tickets) = self.max_tickets {
+            let total_tickets = self.total_tickets.checked_add(count).ok_or(ErrorCode::Overflow)?;
+            require!(total_tickets <= max_tickets, ErrorCode::SoldOut);
+        }
+        if let Some(max_tickets_per_wallet) = self.max_tickets_per_wallet {
+            let buyer_tickets = buyer_tickets.checked_add(count).ok_or(ErrorCode::Overflow)?;
+            require!(buyer_tickets <= max_tickets_per_wallet, ErrorCode::WalletLimitReached);
+        }
+        Ok(())
+    }
+
+    /// Splits the payment for `count` tickets between the pot and the accrued fees.
+    pub fn record_sales(&mut self, count: u64) -> Result<()> {
+        let (amount, fee) = self.sale_amounts(count)?;
+        self.accrued_fees = self.accrued_fees.checked_add(fee).ok_or(ErrorCode::Overflow)?;
+        self.lottery_pot_amount = self.lottery_pot_amount
+            .checked_add(amount - fee)
+            .ok_or(ErrorCode::Overflow)?;
+        Ok(())
+    }
+
+    /// Reverses `record_sales` when tickets are refunded.
+    pub fn record_refunds(&mut self, count: u64) -> Result<()> {
+        let (amount, fee) = self.sale_amounts(count)?;
+        self.accrued_fees = self.accrued_fees.checked_sub(fee).ok_or(ErrorCode::Overflow)?;
+        self.lottery_pot_amount = self.lottery_pot_amount
+            .checked_sub(amount - fee)
+            .ok_or(ErrorCode::Overflow)?;
+        Ok(())
+    }
+
+    /// Price and fee share of `count` tickets. The fee never exceeds the price.
+    pub fn sale_amounts(&self, count: u64) -> Result<(u64, u64)> {
+        let amount = self.price.checked_mul(count).ok_or(ErrorCode::Overflow)?;
+        let fee = self.ticket_fee().checked_mul(count).ok_or(ErrorCode::Overflow)?;
+        Ok((amount, fee))
+    }
+
+    /// Counts a ticket whose record `close_lottery` has to wait for.
+    pub fn record_ticket_opened(&mut self) -> Result<()> {
+        self.open_tickets = self.open_tickets.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        Ok(())
+    }
+
+    /// Counts a ticket whose record has been closed.
+    pub fn record_ticket_closed(&mut self) -> Result<()> {
+        self.open_tickets = self.open_tickets.checked_sub(1).ok_or(ErrorCode::Overflow)?;
+        Ok(())
+    }
+
+    /// Prize tier won by `ticket_id` that can still be claimed.
+    pub fn claimable_tier(&self, ticket_id: u64) -> Result<usize> {
+        require!(self.winner_chosen, ErrorCode::WinnerNotChosen);
+        require!(!self.prize_claimed, ErrorCode::PrizeAlreadyClaimed);
+
+        let tier = self.winning_tier(ticket_id).ok_or(ErrorCode::IncorrectTicket)?;
+        require!(!self.is_tier_claimed(tier), ErrorCode::PrizeAlreadyClaimed);
+        Ok(tier)
+    }
+
+    pub fn mark_tier_claimed(&mut self, tier: usize) {
+        self.claimed_tiers |= 1 << tier;
+        self.prize_claimed = self.all_tiers_claimed();
+    }
+
+    /// Prize tier won by `ticket_id`, if any. Tier 0 is the first prize.
+    pub fn winning_tier(&self, ticket_id: u64) -> Option<usize> {
+        self.winning_ticket_ids.iter().position(|&id| id == ticket_id)
+    }
+
+    pub fn is_tier_claimed(&self, tier: usize) -> bool {
+        self.claimed_tiers & (1 << tier) != 0
+    }
+
+    /// Whether every winning ticket on `receipt` has been paid, so it can be closed.
+    pub fn receipt_settled(&self, receipt: &TicketReceipt) -> bool {
+        self.winning_ticket_ids
+            .iter()
+            .enumerate()
+            .all(|(tier, &ticket_id)| !receipt.contains(ticket_id) || self.is_tier_claimed(tier))
+    }
+
+    pub fn all_tiers_claimed(&self) -> bool {
+        (0..self.winning_ticket_ids.len()).all(|tier| self.is_tier_claimed(tier))
+    }
+
+    /// Whether every prize and fee has been paid out, or every ticket of a cancelled lottery refunded,
+    /// so the lottery account can be closed.
+    pub fn is_settled(&self) -> bool {
+        self.accrued_fees == 0 &&
+            (self.prize_claimed || (self.cancelled && self.lottery_pot_amount == 0))
+    }
+
+    /// Share of the pot paid to `tier`.
+    /// Shares are taken relative to the tiers that were actually drawn, so the whole pot is paid out
+    /// even when fewer tickets were sold than there are tiers.
+    pub fn tier_prize(&self, tier: usize) -> u64 {
+        let drawn_bps: u128 = self.prize_tiers[..self.winning_ticket_ids.len()]
+            .iter()
+            .map(|&bps| bps as u128)
+            .sum();
+        (self.lottery_pot_amount as u128 * self.prize_tiers[tier] as u128 / drawn_bps) as u64
+    }
+}
+
+/// On-chain record of a ticket, written when the ticket is bought.
+#[account]
+#[derive(InitSpace)]
+pub struct TicketRecord {
+    pub bump: u8,
+    pub lottery: Pubkey,
+    pub ticket_index: u64,
+    pub mint: Pubkey,
+    pub purchase_slot: u64,
+}
+
+impl TicketRecord {
+    /// Checks that this record describes `ticket_index` of `lottery`, minted as `mint`.
+    pub fn validate(&self, lottery: &Pubkey, ticket_index: u64, mint: &Pubkey) -> Result<()> {
+        require_keys_eq!(self.lottery, *lottery, ErrorCode::IncorrectTicket);
+        require_keys_eq!(self.mint, *mint, ErrorCode::IncorrectTicket);
+        require!(self.ticket_index == ticket_index, ErrorCode::IncorrectTicket);
+        Ok(())
+    }
+}
+
+/// Number of tickets a wallet has bought in a lottery.
+#[account]
+#[derive(InitSpace)]
+pub struct BuyerState {
+    pub bump: u8,
+    pub lottery: Pubkey,
+    pub buyer: Pubkey,
+    pub tickets_bought: u64,
+}
+
+impl BuyerState {
+    pub fn record_purchase(&mut self, bump: u8, lottery: Pubkey, buyer: Pubkey, count: u64) {
+        self.bump = bump;
+        self.lottery = lottery;
+        self.buyer = buyer;
+        self.tickets_bought += count;
+    }
+}
+
+/// Compact record of a contiguous range of tickets bought with `buy_tickets`.
+#[account]
+#[derive(InitSpace)]
+pub struct TicketReceipt {
+    pub bump: u8,
+    pub lottery: Pubkey,
+    pub owner: Pubkey,
+    pub first_ticket: u64,
+    pub count: u64,
+    pub purchase_slot: u64,
+}
+
+impl TicketReceipt {
+    pub fn contains(&self, ticket_id: u64) -> bool {
+        ticket_id >= self.first_ticket && ticket_id - self.first_ticket < self.count
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct InitializeConfigParams {
+    /// Slot or unix timestamp at which sales open, depending on `schedule_mode`.
+    pub start: u64,
+    /// Last slot or unix timestamp at which tickets can be bought.
+    pub end: u64,
+    pub price: u64,
+    pub fee_bps: u16,
+    pub treasury: Pubkey,
+    /// Share of the pot for each prize tier in basis points, summing to 10000. Defaults to a single winner.
+    pub prize_tiers: Option<Vec<u16>>,
+    /// Slots (or seconds, for timestamp schedules) after `end` from which anyone may cancel an undrawn lottery.
+    pub cancel_timeout: u64,
+    /// What happens to a winning ticket once its prize is claimed.
+    pub winning_ticket_action: WinningTicketAction,
+    /// Total number of tickets that can be sold. `None` means unlimited.
+    pub max_tickets: Option<u64>,
+    /// Number of tickets a single wallet can buy. `None` means unlimited.
+    pub max_tickets_per_wallet: Option<u64>,
+    /// Oracle used to draw the winners.
+    pub randomness_provider: RandomnessProvider,
+    /// SHA-256 of the authority's secret. Required for commit-reveal lotteries.
+    pub secret_commitment: Option<[u8; 32]>,
+    /// Lets anyone commit and reveal the draw once sales have ended.
+    pub permissionless_draw: bool,
+    /// Paid from the accrued fees to a non-authority caller who reveals the draw.
+    pub crank_reward: u64,
+    /// Slots after a commit from which an unfulfilled ORAO request may be replaced.
+    pub recommit_timeout: u64,
+    /// Whether `start`, `end` and `cancel_timeout` are measured in slots or unix time.
+    pub schedule_mode: ScheduleMode,
+    /// Branding of the collection and ticket NFTs. Defaults to `NAME`, `SYMBOL` and `URI`.
+    pub metadata: Option<LotteryMetadata>,
+    /// How ticket NFTs are minted.
+    pub ticket_mode: TicketMode,
+}
+
+/// Metaplex metadata used for the collection NFT and each ticket NFT of a lottery.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+pub struct LotteryMetadata {
+    /// Collection name, and the ticket name before its index (e.g. "Token Lottery Ticket #").
+    #[max_len(MAX_NAME_LENGTH)]
+    pub name_prefix: String,
+    #[max_len(MAX_SYMBOL_LENGTH)]
+    pub symbol: String,
+    #[max_len(MAX_URI_LENGTH)]
+    pub collection_uri: String,
+    /// Ticket metadata URI. Every `{id}` is replaced with the ticket index.
+    #[max_len(MAX_URI_LENGTH)]
+    pub ticket_uri_template: String,
+    /// URI set on winning tickets by `mark_winner`, with the same `{id}` substitution.
+    /// Winning tickets keep their ticket URI when empty.
+    #[max_len(MAX_URI_LENGTH)]
+    pub winner_uri_template: String,
+}
+
+impl Default for LotteryMetadata {
+    fn default() -> Self {
+        LotteryMetadata {
+            name_prefix: NAME.to_string(),
+            symbol: SYMBOL.to_string(),
+            collection_uri: URI.to_string(),
+            ticket_uri_template: URI.to_string(),
+            winner_uri_template: String::new(),
+        }
+    }
+}
+
+impl LotteryMetadata {
+    /// Checks the Metaplex length limits for the widest ticket index the lottery can sell:
+    /// as many digits as `max_tickets`, or `UNCAPPED_TICKET_INDEX_DIGITS` when the supply is
+    /// uncapped.
+    pub fn validate(&self, max_tickets: Option<u64>) -> Result<()> {
+        let index_digits = max_tickets
+            .map_or(UNCAPPED_TICKET_INDEX_DIGITS, |max_tickets| max_tickets.to_string().len());
+        require!(
+            self.name_prefix.len() + index_digits <= MAX_NAME_LENGTH &&
+            self.symbol.len() <= MAX_SYMBOL_LENGTH &&
+            self.collection_uri.len() <= MAX_URI_LENGTH &&
+            Self::rendered_len(&self.ticket_uri_template, index_digits) <= MAX_URI_LENGTH &&
+            Self::rendered_len(&self.winner_uri_template, index_digits) <= MAX_URI_LENGTH,
+            ErrorCode::InvalidMetadata
+        );
+        Ok(())
+    }
+
+    /// Length of `template` once every `{id}` is replaced with an index of `index_digits` digits.
+    fn rendered_len(template: &str, index_digits: usize) -> usize {
+        let placeholders = template.matches(TICKET_ID_PLACEHOLDER).count();
+        template.len() - placeholders * TICKET_ID_PLACEHOLDER.len() + placeholders * index_digits
+    }
+
+    pub fn ticket_name(&self, ticket_id: u64) -> Result<String> {
+        let name = format!("{}{}", self.name_prefix, ticket_id);
+        require!(name.len() <= MAX_NAME_LENGTH, ErrorCode::InvalidMetadata);
+        Ok(name)
+    }
+
+    pub fn ticket_uri(&self, ticket_id: u64) -> Result<String> {
+        Self::substitute(&self.ticket_uri_template, ticket_id)
+    }
+
+    /// Ticket name followed by `WINNER_SUFFIX`, cutting the prefix short if it does not fit.
+    pub fn winner_name(&self, ticket_id: u64) -> String {
+        let ticket_id = ticket_id.to_string();
+        let mut end = self.name_prefix.len()
+            .min(MAX_NAME_LENGTH.saturating_sub(ticket_id.len() + WINNER_SUFFIX.len()));
+        while !self.name_prefix.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}{}{}", &self.name_prefix[..end], ticket_id, WINNER_SUFFIX)
+    }
+
+    pub fn winner_uri(&self, ticket_id: u64) -> Result<String> {
+        if self.winner_uri_template.is_empty() {
+            return self.ticket_uri(ticket_id);
+        }
+        Self::substitute(&self.winner_uri_template, ticket_id)
+    }
+
+    fn substitute(template: &str, ticket_id: u64) -> Result<String> {
+        let uri = template.replace(TICKET_ID_PLACEHOLDER, &ticket_id.to_string());
+        require!(uri.len() <= MAX_URI_LENGTH, ErrorCode::InvalidMetadata);
+        Ok(uri)
+    }
+}
+
+/// Fields left as `None` keep their current value. Only `treasury` and `crank_reward`
+/// can change once tickets have been sold.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct UpdateConfigParams {
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+    pub price: Option<u64>,
+    pub fee_bps: Option<u16>,
+    pub prize_tiers: Option<Vec<u16>>,
+    pub cancel_timeout: Option<u64>,
+    pub winning_ticket_action: Option<WinningTicketAction>,
+    pub treasury: Option<Pubkey>,
+    pub crank_reward: Option<u64>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub enum WinningTicketAction {
+    #[default]
+    Keep,
+    Burn,
+    Freeze,
+}
+
+/// Kind of NFT minted for each ticket.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub enum TicketMode {
+    /// SPL mint with Metaplex metadata and master edition, bought with `buy_ticket`.
+    #[default]
+    TokenMetadata,
+    /// Bubblegum compressed NFT, bought with `buy_compressed_ticket` once `initialize_ticket_tree` has run.
+    Compressed,
+    /// Token-2022 mint carrying its own metadata and a member of the collection's token group,
+    /// bought with `buy_token2022_ticket` once `initialize_token_group` has run.
+    Token2022,
+    /// Metaplex Core asset in the Core collection created by `initialize_lottery`,
+    /// bought with `buy_core_ticket`.
+    Core,
+}
+
+/// Clock the sales window of a lottery is scheduled against.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub enum ScheduleMode {
+    #[default]
+    Slot,
+    /// `Clock::unix_timestamp`, for windows like "ends Friday 20:00 UTC".
+    Timestamp,
+}
+
+impl ScheduleMode {
+    pub fn now(&self, clock: &Clock) -> u64 {
+        match self {
+            ScheduleMode::Slot => clock.slot,
+            ScheduleMode::Timestamp => clock.unix_timestamp.max(0) as u64,
+        }
+    }
+}
+
+/// Oracle a lottery draws its winners from.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum RandomnessProvider {
+    /// Switchboard On-Demand randomness account. `commit_winner` accepts it only if it was
+    /// seeded at most `seed_slot_window` slots ago.
+    Switchboard { seed_slot_window: u64 },
+    /// ORAO VRF `RandomnessV2` request account, seeded with `TokenLottery::orao_request_seed`.
+    Orao,
+    /// No oracle: the authority commits to a secret at initialization and reveals it,
+    /// mixed with buyer entropy and the hash of the slot after `commit_winner`. If the secret
+    /// is not revealed within `COMMIT_REVEAL_DEADLINE_SLOTS`, anyone can draw without it.
+    /// Meant for small community draws.
+    CommitReveal,
+}
+
+impl RandomnessProvider {
+    /// Whether a stuck draw may be recommitted. ORAO fulfils requests on-chain, so a request
+    /// still pending after `recommit_timeout` shows the oracle failed. A Switchboard requester
+    /// gets the reveal off-chain first and could withhold an unwanted value, so a stuck
+    /// Switchboard draw can only be cancelled and refunded.
+    pub fn allows_recommit(&self) -> bool {
+        matches!(self, RandomnessProvider::Orao)
+    }
+}
+
+impl Default for RandomnessProvider {
+    fn default() -> Self {
+        RandomnessProvider::Switchboard { seed_slot_window: DEFAULT_SEED_SLOT_WINDOW }
+    }
+}
+
+/// Parsing and staleness checks for the randomness account of a provider.
+pub trait RandomnessSource {
+    /// Checks that `account` is a fresh request whose value is not known yet, so the lottery can commit to it.
+    fn verify_commit(&self, account: &AccountInfo, clock: &Clock) -> Result<()>;
+
+    /// Returns the 32 bytes of randomness revealed in `account`.
+    fn reveal(&self, account: &AccountInfo, clock: &Clock) -> Result<[u8; 32]>;
+
+    /// Whether the value of `account` is already public, so it must not be replaced.
+    fn is_revealed(&self, account: &AccountInfo) -> Result<bool>;
+}
+
+impl RandomnessSource for RandomnessProvider {
+    fn verify_commit(&self, account: &AccountInfo, clock: &Clock) -> Result<()> {
+        match self {
+            RandomnessProvider::Switchboard { seed_slot_window } => {
+                SwitchboardRandomness { seed_slot_window: *seed_slot_window }.verify_commit(account, clock)
+            }
+            RandomnessProvider::Orao => OraoRandomness.verify_commit(account, clock),
+            RandomnessProvider::CommitReveal => err!(ErrorCode::InvalidRandomnessProvider),
+        }
+    }
+
+    fn reveal(&self, account: &AccountInfo, clock: &Clock) -> Result<[u8; 32]> {
+        match self {
+            RandomnessProvider::Switchboard { seed_slot_window } => {
+                SwitchboardRandomness { seed_slot_window: *seed_slot_window }.reveal(account, clock)
+            }
+            RandomnessProvider::Orao => OraoRandomness.reveal(account, clock),
+            RandomnessProvider::CommitReveal => err!(ErrorCode::InvalidRandomnessProvider),
+        }
+    }
+
+    fn is_revealed(&self, account: &AccountInfo) -> Result<bool> {
+        match self {
+            RandomnessProvider::Switchboard { seed_slot_window } => {
+                SwitchboardRandomness { seed_slot_window: *seed_slot_window }.is_revealed(account)
+            }
+            RandomnessProvider::Orao => OraoRandomness.is_revealed(account),
+            RandomnessProvider::CommitReveal => err!(ErrorCode::InvalidRandomnessProvider),
+        }
+    }
+}
+
+/// Switchboard On-Demand randomness account, owned by the mainnet or devnet on-demand program.
+pub struct SwitchboardRandomness {
+    pub seed_slot_window: u64,
+}
+
+impl SwitchboardRandomness {
+    fn randomness_data<'a>(account: &'a AccountInfo) -> Result<std::cell::Ref<'a, RandomnessAccountData>> {
+        require!(
+            *account.owner == ON_DEMAND_MAINNET_PID || *account.owner == ON_DEMAND_DEVNET_PID,
+            ErrorCode::IncorrectRandomnessAccount
+        );
+
+        RandomnessAccountData::parse(account.try_borrow_data()?)
+            .map_err(|_| error!(ErrorCode::InvalidRandomnessData))
+    }
+}
+
+impl RandomnessSource for SwitchboardRandomness {
+    fn verify_commit(&self, account: &AccountInfo, clock: &Clock) -> Result<()> {
+        let randomness_data = Self::randomness_data(account)?;
+
+        // シードスロットが過去 seed_slot_window スロット以内であること
+        let fresh = clock.slot
+            .checked_sub(randomness_data.seed_slot)
+            .is_some_and(|age| (1..=self.seed_slot_window).contains(&age));
+        require!(fresh, ErrorCode::RandomnessAlreadyRevealed);
+
+        Ok(())
+    }
+
+    fn reveal(&self, account: &AccountInfo, clock: &Clock) -> Result<[u8; 32]> {
+        let randomness_data = Self::randomness_data(account)?;
+        let revealed_random_value = randomness_data.get_value(clock.slot)
+            .map_err(|_| ErrorCode::RandomnessNotResolved)?;
+
+        Ok(revealed_random_value)
+    }
+
+    fn is_revealed(&self, account: &AccountInfo) -> Result<bool> {
+        let randomness_data = Self::randomness_data(account)?;
+        Ok(randomness_data.reveal_slot > randomness_data.seed_slot)
+    }
+}
+
+pub const ORAO_VRF_PROGRAM_ID: Pubkey = pubkey!("VRFzZoJdhFWL8rkvu87LpKM3RbcVezpMEc6X5GVDr7y");
+
+/// Anchor discriminator of ORAO's `RandomnessV2` account, `sha256("account:RandomnessV2")[..8]`.
+pub const ORAO_RANDOMNESS_V2_DISCRIMINATOR: [u8; 8] = [139, 239, 184, 215, 227, 86, 191, 226];
+
+/// ORAO VRF `RandomnessV2` request, as laid out by orao-solana-vrf: the discriminator, then
+/// `Pending { client, seed, responses }` until the request is fulfilled and
+/// `Fulfilled { client, seed, randomness: [u8; 64] }` after.
+pub struct OraoRandomness;
+
+impl OraoRandomness {
+    const PENDING: u8 = 0;
+    const FULFILLED: u8 = 1;
+    const SEED_OFFSET: usize = 8 + 1 + 32;
+    const RANDOMNESS_OFFSET: usize = Self::SEED_OFFSET + 32;
+
+    /// Returns the request seed and, once fulfilled, its 64 bytes of randomness.
+    fn request(account: &AccountInfo) -> Result<([u8; 32], Option<[u8; 64]>)> {
+        require_keys_eq!(*account.owner, ORAO_VRF_PROGRAM_ID, ErrorCode::IncorrectRandomnessAccount);
+
+        let data = account.try_borrow_data()?;
+        require!(
+            data.get(..8) == Some(ORAO_RANDOMNESS_V2_DISCRIMINATOR.as_ref()),
+            ErrorCode::InvalidRandomnessData
+        );
+        let seed = data
+            .get(Self::SEED_OFFSET..Self::SEED_OFFSET + 32)
+            .ok_or(ErrorCode::InvalidRandomnessData)?;
+        let randomness = match data[8] {
+            Self::PENDING => None,
+            Self::FULFILLED => {
+                let randomness = data
+                    .get(Self::RANDOMNESS_OFFSET..Self::RANDOMNESS_OFFSET + 64)
+                    .ok_or(ErrorCode::InvalidRandomnessData)?;
+                Some(randomness.try_into().unwrap())
+            }
+            _ => return err!(ErrorCode::InvalidRandomnessData),
+        };
+
+        Ok((seed.try_into().unwrap(), randomness))
+    }
+
+    pub fn request_seed(account: &AccountInfo) -> Result<[u8; 32]> {
+        Ok(Self::request(account)?.0)
+    }
+}
+
+impl RandomnessSource for OraoRandomness {
+    fn verify_commit(&self, account: &AccountInfo, _clock: &Clock) -> Result<()> {
+        let (_, randomness) = Self::request(account)?;
+        require!(randomness.is_none(), ErrorCode::RandomnessAlreadyRevealed);
+        Ok(())
+    }
+
+    fn reveal(&self, account: &AccountInfo, _clock: &Clock) -> Result<[u8; 32]> {
+        let (_, randomness) = Self::request(account)?;
+        let randomness = randomness.ok_or(ErrorCode::RandomnessNotResolved)?;
+
+        // 64バイトのVRF出力を32バイトに圧縮する
+        Ok(hashv(&[randomness.as_ref()]).to_bytes())
+    }
+
+    fn is_revealed(&self, account: &AccountInfo) -> Result<bool> {
+        Ok(Self::request(account)?.1.is_some())
+    }
+}
+
+#[event]
+pub struct LotteryCreated {
+    pub lottery: Pubkey,
+    pub lottery_id: u64,
+    pub authority: Pubkey,
+    /// `Pubkey::default()` for lotteries priced in SOL.
+    pub payment_mint: Pubkey,
+    pub price: u64,
+    /// Slots or unix timestamps, depending on `schedule_mode`.
+    pub start: u64,
+    pub end: u64,
+    pub schedule_mode: ScheduleMode,
+    pub slot: u64,
+}
+
+#[event]
+pub struct LotteryInitialized {
+    pub lottery: Pubkey,
+    pub collection_mint: Pubkey,
+    pub slot: u64,
+}
+
+/// Emitted for a single ticket NFT as well as for a batch sold on a receipt.
+#[event]
+pub struct TicketPurchased {
+    pub lottery: Pubkey,
+    pub buyer: Pubkey,
+    pub first_ticket_id: u64,
+    pub count: u64,
+    pub amount: u64,
+    pub slot: u64,
+}
+
+#[event]
+pub struct RandomnessCommitted {
+    pub lottery: Pubkey,
+    pub randomness_account: Pubkey,
+    /// The account replaced by `recommit_winner`, if any.
+    pub previous_randomness_account: Option<Pubkey>,
+    pub committer: Pubkey,
+    pub slot: u64,
+}
 
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub token_program: Interface<'info, TokenInterface>,
-    pub system_program: Program<'info, System>,
-    pub token_metadata_program: Program<'info, Metadata>,
-    pub rent: Sysvar<'info, Rent>,
+#[event]
+pub struct WinnerRevealed {
+    pub lottery: Pubkey,
+    pub winning_ticket_ids: Vec<u64>,
+    pub randomness: [u8; 32],
+    pub revealer: Pubkey,
+    pub crank_reward: u64,
+    pub slot: u64,
 }
 
-#[derive(Accounts)]
-pub struct InitializeConfig<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
+#[event]
+pub struct PrizeClaimed {
+    pub lottery: Pubkey,
+    pub winner: Pubkey,
+    pub ticket_id: u64,
+    pub tier: u8,
+    pub amount: u64,
+    pub slot: u64,
+}
 
-    #[account(
-        init,
-        payer = payer,
-        space = 8 + TokenLottery::INIT_SPACE,
-        // Challenge: Make this be able to run more than 1 lottery at a time
-        seeds = [b"token_lottery".as_ref()],
-        bump
-    )]
-    pub token_lottery: Box<Account<'info, TokenLottery>>,
+#[event]
+pub struct WinnerMarked {
+    pub lottery: Pubkey,
+    pub ticket_id: u64,
+    pub tier: u8,
+    pub slot: u64,
+}
 
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct FeesWithdrawn {
+    pub lottery: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,
+    pub slot: u64,
 }
 
-#[derive(Accounts)]
-pub struct InitializeLottery<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
+#[event]
+pub struct LotteryCancelled {
+    pub lottery: Pubkey,
+    pub cancelled_by: Pubkey,
+    pub slot: u64,
+}
 
-    #[account(
-        init,
-        payer = payer,
-        mint::decimals = 0,
-        mint::authority = collection_mint,
-        mint::freeze_authority = collection_mint,
-        seeds = [b"collection_mint".as_ref()],
-        bump,
-    )]
-    pub collection_mint: Box<InterfaceAccount<'info, Mint>>,
+#[event]
+pub struct TicketsRefunded {
+    pub lottery: Pubkey,
+    pub buyer: Pubkey,
+    pub first_ticket_id: u64,
+    pub count: u64,
+    pub amount: u64,
+    pub slot: u64,
+}
 
-    /// CHECK: This account will be initialized by the metaplex program
-    #[account(mut)]
-    pub metadata: UncheckedAccount<'info>,
+#[event]
+pub struct LotteryClosed {
+    pub lottery: Pubkey,
+    pub authority: Pubkey,
+    pub slot: u64,
+}
 
-    /// CHECK: This account will be initialized by the metaplex program
-    #[account(mut)]
-    pub master_edition: UncheckedAccount<'info>,
+#[event]
+pub struct TicketClosed {
+    pub lottery: Pubkey,
+    pub owner: Pubkey,
+    pub ticket_id: u64,
+    pub slot: u64,
+}
 
-    #[account(
-        init_if_needed,
-        payer = payer,
-        seeds = [b"collection_token_account".as_ref()],
-        bump,
-        token::mint = collection_mint,
-        token::authority = collection_token_account
-    )]
-    pub collection_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+#[event]
+pub struct ConfigUpdated {
+    pub lottery: Pubkey,
+    pub authority: Pubkey,
+    pub slot: u64,
+}
 
-    pub token_program: Interface<'info, TokenInterface>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
-    pub token_metadata_program: Program<'info, Metadata>,
-    pub rent: Sysvar<'info, Rent>,
+#[event]
+pub struct AuthorityProposed {
+    pub lottery: Pubkey,
+    pub authority: Pubkey,
+    /// `None` when a pending proposal is withdrawn.
+    pub pending_authority: Option<Pubkey>,
+    pub slot: u64,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct TokenLottery {
-    pub bump: u8,
-    pub winning_ticket_id: u64,
-    pub winner_chosen: bool,
-    pub lottery_start: u64,
-    pub lottery_end: u64,
-    pub lottery_pot_amount: u64,
-    pub total_tickets: u64,
-    pub price: u64,
-    pub randomness_account: Pubkey,
+#[event]
+pub struct AuthorityTransferred {
+    pub lottery: Pubkey,
+    pub previous_authority: Pubkey,
     pub authority: Pubkey,
+    pub slot: u64,
+}
+
+#[event]
+pub struct PausedSet {
+    pub lottery: Pubkey,
+    pub paused: bool,
+    pub slot: u64,
+}
+
+#[event]
+pub struct TicketTreeInitialized {
+    pub lottery: Pubkey,
+    pub merkle_tree: Pubkey,
+    pub max_depth: u32,
+    pub slot: u64,
 }
 
 #[error_code]
@@ -672,4 +5611,840 @@ pub enum ErrorCode {
     NoTicketsSold,
     #[msg("Ticket has no collection")]
     NoCollection,
-}
\ No newline at end of file
+    #[msg("Payment accounts are required for token lotteries")]
+    MissingPaymentAccounts,
+    #[msg("Incorrect payment mint")]
+    IncorrectPaymentMint,
+    #[msg("Fee exceeds 100%")]
+    InvalidFee,
+    #[msg("No fees accrued")]
+    NoFeesAccrued,
+    #[msg("Prize tiers must be non-empty and sum to 10000 basis points")]
+    InvalidPrizeTiers,
+    #[msg("Prize already claimed")]
+    PrizeAlreadyClaimed,
+    #[msg("Lottery is cancelled")]
+    LotteryCancelled,
+    #[msg("Lottery is not cancelled")]
+    LotteryNotCancelled,
+    #[msg("Invalid ticket count")]
+    InvalidTicketCount,
+    #[msg("All tickets have been sold")]
+    SoldOut,
+    #[msg("Wallet has reached its ticket limit")]
+    WalletLimitReached,
+    #[msg("Commit-reveal lotteries require a secret commitment")]
+    MissingSecretCommitment,
+    #[msg("Secret does not match the commitment")]
+    IncorrectSecret,
+    #[msg("Instruction not supported by this randomness provider")]
+    InvalidRandomnessProvider,
+    #[msg("Randomness account data could not be parsed")]
+    InvalidRandomnessData,
+    #[msg("Seed slot window must be greater than zero")]
+    InvalidSeedSlotWindow,
+    #[msg("Randomness already committed")]
+    RandomnessAlreadyCommitted,
+    #[msg("Randomness not committed")]
+    RandomnessNotCommitted,
+    #[msg("Recommit timeout has not passed")]
+    RecommitTooEarly,
+    #[msg("Too many recommits")]
+    TooManyRecommits,
+    #[msg("Config cannot change after tickets are sold or the draw has started")]
+    ConfigLocked,
+    #[msg("Lottery is paused")]
+    LotteryPaused,
+    #[msg("Prizes, fees or refunds are still outstanding")]
+    LotteryNotSettled,
+    #[msg("Winning ticket has an unclaimed prize")]
+    UnclaimedPrize,
+    #[msg("Lottery must start before it ends and end in the future")]
+    InvalidSchedule,
+    #[msg("Ticket name, symbol or URI exceeds the metadata length limits")]
+    InvalidMetadata,
+    #[msg("Instruction does not match the lottery's ticket mode")]
+    InvalidTicketMode,
+    #[msg("Ticket tree already initialized")]
+    TicketTreeAlreadyInitialized,
+    #[msg("Core collection and mpl-core program accounts are required")]
+    MissingCoreAccounts,
+    #[msg("Payment mint has unsupported Token-2022 extensions")]
+    UnsupportedPaymentMint,
+    #[msg("Arithmetic overflow")]
+    Overflow,
+    #[msg("Slot hash for the reveal slot is no longer available")]
+    SlotHashExpired,
+    #[msg("Ticket records must be closed before the lottery")]
+    TicketsStillOpen,
+    #[msg("Collection token account, metadata, edition and programs are required")]
+    MissingCollectionAccounts,
+    #[msg("Only an unfulfilled ORAO request can be recommitted; cancel the lottery instead")]
+    RecommitNotAllowed,
+    #[msg("The secret can no longer be revealed; draw without it")]
+    RevealDeadlinePassed,
+    #[msg("Only the secret can be revealed until the reveal deadline")]
+    RevealDeadlineNotReached,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic stand-in for Switchboard randomness.
+    fn randomness(round: u64) -> [u8; 32] {
+        hashv(&[b"randomness".as_ref(), round.to_le_bytes().as_ref()]).to_bytes()
+    }
+
+    /// Pearson chi-squared statistic of `counts` against a uniform distribution.
+    fn chi_squared(counts: &[u64]) -> f64 {
+        let total: u64 = counts.iter().sum();
+        let expected = total as f64 / counts.len() as f64;
+        counts
+            .iter()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    /// Local provider that reveals a fixed value once it has been "fulfilled".
+    struct MockRandomness {
+        value: Option<[u8; 32]>,
+    }
+
+    impl RandomnessSource for MockRandomness {
+        fn verify_commit(&self, _account: &AccountInfo, _clock: &Clock) -> Result<()> {
+            require!(self.value.is_none(), ErrorCode::RandomnessAlreadyRevealed);
+            Ok(())
+        }
+
+        fn reveal(&self, _account: &AccountInfo, _clock: &Clock) -> Result<[u8; 32]> {
+            self.value.ok_or(ErrorCode::RandomnessNotResolved.into())
+        }
+
+        fn is_revealed(&self, _account: &AccountInfo) -> Result<bool> {
+            Ok(self.value.is_some())
+        }
+    }
+
+    fn with_account<T>(owner: Pubkey, mut data: Vec<u8>, f: impl FnOnce(&AccountInfo) -> T) -> T {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        f(&account)
+    }
+
+    fn lottery(total_tickets: u64, prize_tiers: Vec<u16>) -> TokenLottery {
+        TokenLottery {
+            total_tickets,
+            prize_tiers,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn mock_provider_drives_a_draw() {
+        let clock = Clock::default();
+        let mut token_lottery = lottery(10, vec![5000, 3000, 2000]);
+
+        let pending = MockRandomness { value: None };
+        with_account(Pubkey::new_unique(), vec![], |account| {
+            assert!(pending.verify_commit(account, &clock).is_ok());
+            assert!(pending.reveal(account, &clock).is_err());
+        });
+
+        let fulfilled = MockRandomness { value: Some(randomness(7)) };
+        let value = with_account(Pubkey::new_unique(), vec![], |account| {
+            assert!(fulfilled.verify_commit(account, &clock).is_err());
+            fulfilled.reveal(account, &clock).unwrap()
+        });
+
+        token_lottery.record_draw(&value);
+        assert!(token_lottery.winner_chosen);
+        assert_eq!(token_lottery.winning_ticket_ids, draw_winners(&value, 10, 3));
+    }
+
+    fn orao_request(seed: [u8; 32], randomness: Option<[u8; 64]>) -> Vec<u8> {
+        let mut data = ORAO_RANDOMNESS_V2_DISCRIMINATOR.to_vec();
+        data.push(if randomness.is_some() { OraoRandomness::FULFILLED } else { OraoRandomness::PENDING });
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(&seed);
+        match randomness {
+            Some(randomness) => data.extend_from_slice(&randomness),
+            None => data.extend_from_slice(&0u32.to_le_bytes()),
+        }
+        data
+    }
+
+    #[test]
+    fn orao_requests_are_committed_unfulfilled_and_revealed_fulfilled() {
+        let clock = Clock::default();
+        assert_eq!(
+            ORAO_RANDOMNESS_V2_DISCRIMINATOR,
+            hashv(&[b"account:RandomnessV2".as_ref()]).to_bytes()[..8]
+        );
+
+        with_account(ORAO_VRF_PROGRAM_ID, orao_request([0; 32], None), |account| {
+            assert!(OraoRandomness.verify_commit(account, &clock).is_ok());
+            assert!(OraoRandomness.reveal(account, &clock).is_err());
+            assert!(!OraoRandomness.is_revealed(account).unwrap());
+        });
+
+        let fulfilled = orao_request([0; 32], Some([1; 64]));
+        with_account(ORAO_VRF_PROGRAM_ID, fulfilled.clone(), |account| {
+            assert!(OraoRandomness.verify_commit(account, &clock).is_err());
+            assert!(OraoRandomness.reveal(account, &clock).is_ok());
+            assert!(OraoRandomness.is_revealed(account).unwrap());
+        });
+
+        // Accounts owned by another program, or of another account type, are rejected
+        with_account(Pubkey::new_unique(), fulfilled.clone(), |account| {
+            assert!(OraoRandomness.reveal(account, &clock).is_err());
+        });
+        let mut legacy = fulfilled;
+        legacy[..8].copy_from_slice(&hashv(&[b"account:Randomness".as_ref()]).to_bytes()[..8]);
+        with_account(ORAO_VRF_PROGRAM_ID, legacy, |account| {
+            assert!(OraoRandomness.reveal(account, &clock).is_err());
+        });
+    }
+
+    #[test]
+    fn orao_commits_need_a_request_seeded_after_sales_closed() {
+        let clock = Clock::default();
+        let mut token_lottery = TokenLottery {
+            randomness_provider: RandomnessProvider::Orao,
+            total_tickets: 3,
+            ..Default::default()
+        };
+
+        // A request seeded while tickets were still on sale no longer matches
+        let early = orao_request(token_lottery.orao_request_seed(), None);
+        token_lottery.mix_entropy(&Pubkey::new_unique(), &[7; 32]);
+        token_lottery.total_tickets += 1;
+        with_account(ORAO_VRF_PROGRAM_ID, early, |account| {
+            assert!(token_lottery.verify_commit(account, &clock).is_err());
+        });
+
+        let request = orao_request(token_lottery.orao_request_seed(), None);
+        with_account(ORAO_VRF_PROGRAM_ID, request.clone(), |account| {
+            assert!(token_lottery.verify_commit(account, &clock).is_ok());
+        });
+
+        // Each recommit needs a fresh request
+        token_lottery.record_commit(Pubkey::new_unique(), 10);
+        with_account(ORAO_VRF_PROGRAM_ID, request, |account| {
+            assert!(token_lottery.verify_commit(account, &clock).is_err());
+        });
+    }
+
+    #[test]
+    fn switchboard_commit_checks_owner_data_and_seed_slot() {
+        let provider = SwitchboardRandomness { seed_slot_window: 10 };
+        let clock = Clock { slot: 100, ..Clock::default() };
+
+        // discriminator + authority + queue + seed_slothash の後に seed_slot が続く
+        let seeded_at = |seed_slot: u64| {
+            let mut data = vec![0u8; 8 + std::mem::size_of::<RandomnessAccountData>()];
+            data[..8].copy_from_slice(<RandomnessAccountData as switchboard_on_demand::Discriminator>::DISCRIMINATOR);
+            data[8 + 96..8 + 104].copy_from_slice(&seed_slot.to_le_bytes());
+            data
+        };
+
+        with_account(ON_DEMAND_MAINNET_PID, seeded_at(99), |account| {
+            assert!(provider.verify_commit(account, &clock).is_ok());
+        });
+        with_account(ON_DEMAND_DEVNET_PID, seeded_at(90), |account| {
+            assert!(provider.verify_commit(account, &clock).is_ok());
+        });
+        for stale in [89, 100, 101] {
+            with_account(ON_DEMAND_MAINNET_PID, seeded_at(stale), |account| {
+                assert!(provider.verify_commit(account, &clock).is_err());
+            });
+        }
+        with_account(Pubkey::new_unique(), seeded_at(99), |account| {
+            assert!(provider.verify_commit(account, &clock).is_err());
+        });
+        with_account(ON_DEMAND_MAINNET_PID, vec![1, 2, 3], |account| {
+            assert!(provider.verify_commit(account, &clock).is_err());
+            assert!(provider.reveal(account, &clock).is_err());
+        });
+
+        // スロット0でもアンダーフローしない
+        with_account(ON_DEMAND_MAINNET_PID, seeded_at(0), |account| {
+            assert!(provider.verify_commit(account, &Clock::default()).is_err());
+        });
+
+        // reveal_slot は seed_slot と oracle の後に続く
+        let mut revealed = seeded_at(99);
+        revealed[8 + 136..8 + 144].copy_from_slice(&100u64.to_le_bytes());
+        with_account(ON_DEMAND_MAINNET_PID, seeded_at(99), |account| {
+            assert!(!provider.is_revealed(account).unwrap());
+        });
+        with_account(ON_DEMAND_MAINNET_PID, revealed, |account| {
+            assert!(provider.is_revealed(account).unwrap());
+        });
+    }
+
+    #[test]
+    fn recommit_waits_for_the_timeout_and_rejects_reused_or_revealed_randomness() {
+        let mut token_lottery = TokenLottery {
+            recommit_timeout: 100,
+            randomness_provider: RandomnessProvider::Orao,
+            ..lottery(10, vec![TOTAL_PRIZE_BPS])
+        };
+        let first = Pubkey::new_unique();
+        let second = Pubkey::new_unique();
+        assert_eq!(
+            token_lottery.check_can_recommit(&second, false, 500).unwrap_err(),
+            ErrorCode::RandomnessNotCommitted.into()
+        );
+
+        // A Switchboard requester could withhold the reveal, so only ORAO draws are rerolled
+        for randomness_provider in [RandomnessProvider::default(), RandomnessProvider::CommitReveal] {
+            let mut withheld = TokenLottery { randomness_provider, ..token_lottery.clone() };
+            withheld.record_commit(first, 100);
+            assert_eq!(
+                withheld.check_can_recommit(&second, false, 10_000).unwrap_err(),
+                ErrorCode::RecommitNotAllowed.into()
+            );
+        }
+
+        token_lottery.record_commit(first, 100);
+        assert_eq!(
+            token_lottery.check_can_recommit(&second, false, 200).unwrap_err(),
+            ErrorCode::RecommitTooEarly.into()
+        );
+        assert_eq!(
+            token_lottery.check_can_recommit(&second, true, 201).unwrap_err(),
+            ErrorCode::RandomnessAlreadyRevealed.into()
+        );
+        assert_eq!(
+            token_lottery.check_can_recommit(&first, false, 201).unwrap_err(),
+            ErrorCode::IncorrectRandomnessAccount.into()
+        );
+        assert!(token_lottery.check_can_recommit(&second, false, 201).is_ok());
+
+        token_lottery.randomness_history.push(first);
+        token_lottery.record_commit(second, 250);
+        assert_eq!(token_lottery.randomness_history, vec![first]);
+
+        // Accounts that were replaced earlier cannot come back
+        assert_eq!(
+            token_lottery.check_can_recommit(&first, false, 351).unwrap_err(),
+            ErrorCode::IncorrectRandomnessAccount.into()
+        );
+
+        // The history is capped
+        while token_lottery.randomness_history.len() < MAX_RANDOMNESS_HISTORY as usize {
+            token_lottery.randomness_history.push(Pubkey::new_unique());
+        }
+        assert_eq!(
+            token_lottery.check_can_recommit(&Pubkey::new_unique(), false, 351).unwrap_err(),
+            ErrorCode::TooManyRecommits.into()
+        );
+    }
+
+    #[test]
+    fn commit_reveal_checks_the_secret_and_mixes_entropy() {
+        let secret = [7u8; 32];
+        let slot_hash = [9u8; 32];
+        let mut token_lottery = TokenLottery {
+            randomness_provider: RandomnessProvider::CommitReveal,
+            secret_commitment: hashv(&[secret.as_ref()]).to_bytes(),
+            ..Default::default()
+        };
+
+        assert!(token_lottery.reveal_secret(&[8u8; 32], &slot_hash).is_err());
+
+        let without_entropy = token_lottery.reveal_secret(&secret, &slot_hash).unwrap();
+        token_lottery.mix_entropy(&Pubkey::new_unique(), &[1u8; 32]);
+        let with_entropy = token_lottery.reveal_secret(&secret, &slot_hash).unwrap();
+        assert_ne!(without_entropy, with_entropy);
+    }
+
+    #[test]
+    fn commit_reveal_uses_the_hash_of_the_committed_slot() {
+        fn slot_hashes(entries: &[(u64, u8)]) -> Vec<u8> {
+            let mut data = (entries.len() as u64).to_le_bytes().to_vec();
+            for (slot, hash) in entries {
+                data.extend_from_slice(&slot.to_le_bytes());
+                data.extend_from_slice(&[*hash; 32]);
+            }
+            data
+        }
+
+        let mut token_lottery = TokenLottery { randomness_provider: RandomnessProvider::CommitReveal, ..Default::default() };
+        token_lottery.record_commit(slot_hashes::ID, 100);
+        assert_eq!(token_lottery.reveal_slot, 101);
+
+        // Not produced yet
+        with_account(Pubkey::default(), slot_hashes(&[(100, 1), (99, 2)]), |account| {
+            assert_eq!(slot_hash_at(account, 101).unwrap_err(), ErrorCode::RandomnessNotResolved.into());
+        });
+        // Newer blocks do not change the hash that is used
+        with_account(Pubkey::default(), slot_hashes(&[(103, 4), (101, 3), (100, 1)]), |account| {
+            assert_eq!(slot_hash_at(account, 101).unwrap(), [3; 32]);
+        });
+        // A skipped reveal slot falls to the next block
+        with_account(Pubkey::default(), slot_hashes(&[(103, 4), (102, 3), (100, 1)]), |account| {
+            assert_eq!(slot_hash_at(account, 101).unwrap(), [3; 32]);
+        });
+        // Once older entries are gone the first block cannot be told apart from a later one
+        with_account(Pubkey::default(), slot_hashes(&[(103, 4), (102, 3)]), |account| {
+            assert_eq!(slot_hash_at(account, 101).unwrap_err(), ErrorCode::SlotHashExpired.into());
+        });
+
+        // The secret uses the reveal slot until the deadline, then anyone can draw from the deadline slot
+        assert_eq!(token_lottery.commit_reveal_hash_slot(true, 612).unwrap(), 101);
+        assert_eq!(
+            token_lottery.commit_reveal_hash_slot(false, 613).unwrap_err(),
+            ErrorCode::RevealDeadlineNotReached.into()
+        );
+        assert_eq!(
+            token_lottery.commit_reveal_hash_slot(true, 613).unwrap_err(),
+            ErrorCode::RevealDeadlinePassed.into()
+        );
+        assert_eq!(token_lottery.commit_reveal_hash_slot(false, 614).unwrap(), 613);
+        assert_ne!(token_lottery.fallback_randomness(&[3; 32]), token_lottery.fallback_randomness(&[4; 32]));
+
+        // Commit-reveal lotteries commit to the SlotHashes sysvar and cannot be recommitted
+        with_account(Pubkey::default(), vec![], |account| {
+            assert!(token_lottery.verify_commit(account, &Clock::default()).is_err());
+            assert!(token_lottery.randomness_provider.is_revealed(account).is_err());
+        });
+    }
+
+    #[test]
+    fn authority_can_only_cancel_before_commit() {
+        let authority = Pubkey::new_unique();
+        let anyone = Pubkey::new_unique();
+        let mut token_lottery = TokenLottery {
+            authority,
+            lottery_end: 100,
+            cancel_timeout: 50,
+            ..Default::default()
+        };
+
+        assert!(token_lottery.check_can_cancel(&authority, 120).is_ok());
+        assert!(token_lottery.check_can_cancel(&anyone, 120).is_err());
+
+        token_lottery.record_commit(Pubkey::new_unique(), 110);
+        assert!(token_lottery.check_can_cancel(&authority, 120).is_err());
+        assert!(token_lottery.check_can_cancel(&authority, 151).is_ok());
+        assert!(token_lottery.check_can_cancel(&anyone, 151).is_ok());
+
+        token_lottery.cancelled = true;
+        assert!(token_lottery.check_can_cancel(&anyone, 151).is_err());
+    }
+
+    #[test]
+    fn crank_reward_is_capped_at_accrued_fees() {
+        let authority = Pubkey::new_unique();
+        let mut token_lottery = TokenLottery { authority, crank_reward: 1_500, accrued_fees: 2_000, ..Default::default() };
+
+        assert!(token_lottery.check_can_draw(&authority).is_ok());
+        assert!(token_lottery.check_can_draw(&Pubkey::new_unique()).is_err());
+        token_lottery.permissionless_draw = true;
+        assert!(token_lottery.check_can_draw(&Pubkey::new_unique()).is_ok());
+
+        assert_eq!(token_lottery.take_crank_reward(), 1_500);
+        assert_eq!(token_lottery.take_crank_reward(), 500);
+        assert_eq!(token_lottery.take_crank_reward(), 0);
+        assert_eq!(token_lottery.accrued_fees, 0);
+    }
+
+    #[test]
+    fn receipts_settle_once_their_winning_tickets_are_paid() {
+        let mut token_lottery = lottery(20, vec![5000, 5000]);
+        token_lottery.winning_ticket_ids = vec![3, 12];
+        let receipt = |first_ticket| TicketReceipt {
+            bump: 0,
+            lottery: Pubkey::default(),
+            owner: Pubkey::default(),
+            first_ticket,
+            count: 5,
+            purchase_slot: 0,
+        };
+        let losing = receipt(5);
+        let receipt = receipt(0);
+
+        assert!(token_lottery.receipt_settled(&losing));
+        assert!(!token_lottery.receipt_settled(&receipt));
+
+        token_lottery.mark_tier_claimed(1);
+        assert!(!token_lottery.receipt_settled(&receipt));
+        token_lottery.mark_tier_claimed(0);
+        assert!(token_lottery.receipt_settled(&receipt));
+    }
+
+    #[test]
+    fn sale_terms_lock_once_the_draw_can_start() {
+        let clock = Clock { slot: 50, ..Clock::default() };
+        let open = TokenLottery { lottery_end: 100, ..Default::default() };
+        assert!(open.check_sale_terms_unlocked(&clock).is_ok());
+
+        let locked = [
+            TokenLottery { total_tickets: 1, ..open.clone() },
+            TokenLottery { lottery_end: 10, ..open.clone() },
+            TokenLottery { randomness_account: Pubkey::new_unique(), ..open.clone() },
+            TokenLottery { winner_chosen: true, ..open.clone() },
+            TokenLottery { cancelled: true, ..open.clone() },
+        ];
+        for token_lottery in locked {
+            assert_eq!(
+                token_lottery.check_sale_terms_unlocked(&clock).unwrap_err(),
+                ErrorCode::ConfigLocked.into()
+            );
+        }
+    }
+
+    #[test]
+    fn paused_lottery_stops_sales() {
+        let clock = Clock { slot: 50, ..Clock::default() };
+        let mut token_lottery = TokenLottery { lottery_end: 100, ..Default::default() };
+        assert!(token_lottery.check_sales_open(&clock).is_ok());
+
+        token_lottery.paused = true;
+        assert!(token_lottery.check_sales_open(&clock).is_err());
+    }
+
+    #[test]
+    fn ticket_limits_reject_overflowing_counts() {
+        let token_lottery = TokenLottery {
+            total_tickets: 5,
+            max_tickets: Some(10),
+            max_tickets_per_wallet: Some(3),
+            ..Default::default()
+        };
+        assert!(token_lottery.check_ticket_limits(1, 2).is_ok());
+        assert_eq!(token_lottery.check_ticket_limits(2, 2).unwrap_err(), ErrorCode::WalletLimitReached.into());
+        assert_eq!(token_lottery.check_ticket_limits(0, u64::MAX).unwrap_err(), ErrorCode::Overflow.into());
+
+        let uncapped_supply = TokenLottery { max_tickets: None, ..token_lottery };
+        assert_eq!(uncapped_supply.check_ticket_limits(1, u64::MAX).unwrap_err(), ErrorCode::Overflow.into());
+    }
+
+    #[test]
+    fn accounting_mismatches_fail_with_an_error() {
+        let mut token_lottery = TokenLottery { price: 100, fee_bps: 1000, ..Default::default() };
+        token_lottery.record_sales(2).unwrap();
+        assert_eq!(token_lottery.accrued_fees, 20);
+        assert_eq!(token_lottery.lottery_pot_amount, 180);
+        assert_eq!(token_lottery.sale_amounts(u64::MAX).unwrap_err(), ErrorCode::Overflow.into());
+
+        token_lottery.record_refunds(2).unwrap();
+        assert_eq!(token_lottery.lottery_pot_amount, 0);
+        assert_eq!(token_lottery.record_refunds(1).unwrap_err(), ErrorCode::Overflow.into());
+
+        token_lottery.record_ticket_opened().unwrap();
+        token_lottery.record_ticket_closed().unwrap();
+        assert_eq!(token_lottery.record_ticket_closed().unwrap_err(), ErrorCode::Overflow.into());
+    }
+
+    #[test]
+    fn timestamp_schedules_follow_unix_time() {
+        let clock = Clock { slot: 1_000, unix_timestamp: 1_700_000_000, ..Clock::default() };
+        let token_lottery = TokenLottery {
+            lottery_start: 1_699_999_000,
+            lottery_end: 1_700_000_500,
+            schedule_mode: ScheduleMode::Timestamp,
+            ..Default::default()
+        };
+
+        assert!(token_lottery.validate_schedule(&clock).is_ok());
+        assert!(token_lottery.check_sales_open(&clock).is_ok());
+        assert!(!token_lottery.sales_ended(&clock));
+
+        let later = Clock { unix_timestamp: 1_700_000_501, ..clock.clone() };
+        assert!(token_lottery.check_sales_open(&later).is_err());
+        assert!(token_lottery.sales_ended(&later));
+        assert!(token_lottery.validate_schedule(&later).is_err());
+
+        // The same window read as slots ended long ago
+        let slot_lottery = TokenLottery { schedule_mode: ScheduleMode::Slot, ..token_lottery.clone() };
+        assert!(slot_lottery.check_sales_open(&clock).is_err());
+
+        let empty_window = TokenLottery { lottery_start: 1_700_000_500, ..token_lottery };
+        assert!(empty_window.validate_schedule(&clock).is_err());
+    }
+
+    #[test]
+    fn schedules_must_start_before_they_end() {
+        let clock = Clock { slot: 1_000, unix_timestamp: 1_700_000_000, ..Clock::default() };
+        for (schedule_mode, end) in [(ScheduleMode::Slot, 2_000), (ScheduleMode::Timestamp, 1_700_001_000)] {
+            let token_lottery = TokenLottery { lottery_end: end, schedule_mode, ..Default::default() };
+            assert!(token_lottery.validate_schedule(&clock).is_ok());
+
+            for lottery_start in [end, end + 1] {
+                let inverted = TokenLottery { lottery_start, ..token_lottery.clone() };
+                assert_eq!(inverted.validate_schedule(&clock).unwrap_err(), ErrorCode::InvalidSchedule.into());
+            }
+        }
+    }
+
+    #[test]
+    fn ticket_metadata_substitutes_the_ticket_index() {
+        let metadata = LotteryMetadata {
+            name_prefix: "Friday Draw #".to_string(),
+            symbol: "FRI".to_string(),
+            collection_uri: "https://example.com/friday.json".to_string(),
+            ticket_uri_template: "https://example.com/friday/{id}.json".to_string(),
+            winner_uri_template: "https://example.com/friday/{id}-winner.json".to_string(),
+        };
+        assert!(metadata.validate(Some(1_000)).is_ok());
+        assert_eq!(metadata.ticket_name(42).unwrap(), "Friday Draw #42");
+        assert_eq!(metadata.ticket_uri(42).unwrap(), "https://example.com/friday/42.json");
+        assert_eq!(metadata.winner_name(42), "Friday Draw #42 WINNER");
+        assert_eq!(metadata.winner_uri(42).unwrap(), "https://example.com/friday/42-winner.json");
+
+        // The default prefix is "Token Lottery Ticket #", so the index may take 10 digits
+        let defaults = LotteryMetadata::default();
+        assert!(defaults.validate(None).is_ok());
+        assert!(defaults.ticket_name(9_999_999_999).is_ok());
+        assert!(defaults.ticket_name(10_000_000_000).is_err());
+        assert_eq!(defaults.winner_uri(7).unwrap(), defaults.ticket_uri(7).unwrap());
+
+        // Long names are cut short so the suffix always fits
+        let winner_name = defaults.winner_name(123_456);
+        assert_eq!(winner_name, "Token Lottery Ticke123456 WINNER");
+        assert!(winner_name.len() <= MAX_NAME_LENGTH);
+
+        let long_symbol = LotteryMetadata { symbol: "TOOLONGSYMBOL".to_string(), ..metadata };
+        assert!(long_symbol.validate(Some(10)).is_err());
+    }
+
+    #[test]
+    fn accepted_ticket_metadata_renders_for_every_ticket_index() {
+        let templates = [
+            ("Token Lottery Ticket #".to_string(), "x".repeat(196) + "{id}"),
+            ("Friday Draw #".to_string(), "https://example.com/{id}/{id}.json".to_string()),
+            ("A".repeat(MAX_NAME_LENGTH - 1), URI.to_string()),
+            (NAME.to_string(), "x".repeat(MAX_URI_LENGTH - 20) + "{id}"),
+        ];
+        let mut accepted = 0;
+        for (name_prefix, ticket_uri_template) in templates {
+            let metadata = LotteryMetadata {
+                name_prefix,
+                winner_uri_template: ticket_uri_template.replace("{id}", "{id}{id}"),
+                ticket_uri_template,
+                ..LotteryMetadata::default()
+            };
+            for max_tickets in [Some(1), Some(10), Some(9_999), Some(10_000), Some(u64::MAX), None] {
+                if metadata.validate(max_tickets).is_err() {
+                    continue;
+                }
+                accepted += 1;
+
+                let widest = max_tickets.map_or(9_999_999_999, |max_tickets| max_tickets - 1);
+                for ticket_id in [0, widest] {
+                    assert!(metadata.ticket_name(ticket_id).is_ok());
+                    assert!(metadata.ticket_uri(ticket_id).is_ok());
+                    assert!(metadata.winner_uri(ticket_id).is_ok());
+                }
+            }
+        }
+        assert!(accepted > 0);
+
+        // Templates that would stop fitting partway through a sale are rejected up front
+        let long_uri = LotteryMetadata { ticket_uri_template: "x".repeat(196) + "{id}", ..LotteryMetadata::default() };
+        assert!(long_uri.validate(Some(9_999)).is_ok());
+        assert!(long_uri.validate(Some(10_000)).is_err());
+        assert!(long_uri.validate(None).is_err());
+
+        // Uncapped lotteries need a prefix that leaves room for `UNCAPPED_TICKET_INDEX_DIGITS`
+        let long_name = LotteryMetadata { name_prefix: "Saturday Lottery Ticket #".to_string(), ..LotteryMetadata::default() };
+        assert!(long_name.validate(Some(9_999_999)).is_ok());
+        assert!(long_name.validate(None).is_err());
+    }
+
+    #[test]
+    fn compressed_ticket_leaf_binds_ticket_owner_and_position() {
+        let metadata = LotteryMetadata::default();
+        let collection_mint = Pubkey::new_unique();
+        let merkle_tree = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let leaf = |ticket_id, nonce, owner| {
+            compressed_ticket_leaf(&metadata, collection_mint, ticket_id, &merkle_tree, nonce, owner, owner).unwrap()
+        };
+
+        assert_eq!(leaf(3, 3, owner), leaf(3, 3, owner));
+        assert_ne!(leaf(3, 3, owner), leaf(4, 3, owner));
+        assert_ne!(leaf(3, 3, owner), leaf(3, 4, owner));
+        assert_ne!(leaf(3, 3, owner), leaf(3, 3, Pubkey::new_unique()));
+
+        // The leaf stores the collection as verified by Bubblegum during the mint
+        let minted = compressed_ticket_metadata(&metadata, collection_mint, 3, false).unwrap();
+        assert_eq!(minted.name, "Token Lottery Ticket #3");
+        assert_ne!(
+            hash_metadata(&minted).unwrap(),
+            compressed_ticket_data_hash(&metadata, collection_mint, 3).unwrap()
+        );
+        assert_eq!(leaf_index(3).unwrap(), 3);
+        assert!(leaf_index(u64::from(u32::MAX) + 1).is_err());
+    }
+
+    #[test]
+    fn core_ticket_owner_reads_live_assets_only() {
+        let owner = Pubkey::new_unique();
+        let asset = |key| {
+            BaseAssetV1 {
+                key,
+                owner,
+                update_authority: mpl_core::types::UpdateAuthority::None,
+                name: "Token Lottery Ticket #0".to_string(),
+                uri: URI.to_string(),
+                seq: None,
+            }.try_to_vec().unwrap()
+        };
+
+        let live = with_account(mpl_core::ID, asset(mpl_core::types::Key::AssetV1), core_ticket_owner);
+        assert_eq!(live.unwrap(), owner);
+
+        // Burning leaves a single uninitialized key byte behind
+        let burned = with_account(mpl_core::ID, vec![0], core_ticket_owner);
+        assert!(burned.is_err());
+
+        let collection = with_account(mpl_core::ID, asset(mpl_core::types::Key::CollectionV1), core_ticket_owner);
+        assert!(collection.is_err());
+    }
+
+    #[test]
+    fn compressed_tickets_are_kept_after_claiming() {
+        let mut token_lottery = TokenLottery { ticket_mode: TicketMode::Compressed, ..Default::default() };
+        assert!(token_lottery.validate_ticket_mode().is_ok());
+
+        token_lottery.winning_ticket_action = WinningTicketAction::Burn;
+        assert!(token_lottery.validate_ticket_mode().is_err());
+
+        token_lottery.ticket_mode = TicketMode::TokenMetadata;
+        assert!(token_lottery.validate_ticket_mode().is_ok());
+    }
+
+    #[test]
+    fn payment_mints_may_only_carry_metadata_extensions() {
+        use anchor_spl::token_2022::spl_token_2022::extension::{
+            metadata_pointer::MetadataPointer, transfer_fee::TransferFeeConfig, BaseStateWithExtensionsMut,
+            StateWithExtensionsMut,
+        };
+
+        fn mint_with(extension: ExtensionType, init: impl FnOnce(&mut StateWithExtensionsMut<MintState>)) -> Vec<u8> {
+            let len = ExtensionType::try_calculate_account_len::<MintState>(&[extension]).unwrap();
+            let mut data = vec![0; len];
+            let mut mint = StateWithExtensionsMut::<MintState>::unpack_uninitialized(&mut data).unwrap();
+            init(&mut mint);
+            mint.base = MintState { is_initialized: true, ..Default::default() };
+            mint.pack_base();
+            mint.init_account_type().unwrap();
+            data
+        }
+
+        let transfer_fee = mint_with(ExtensionType::TransferFeeConfig, |mint| {
+            mint.init_extension::<TransferFeeConfig>(true).unwrap();
+        });
+        assert!(with_account(Token2022::id(), transfer_fee, validate_payment_mint).is_err());
+
+        let metadata_pointer = mint_with(ExtensionType::MetadataPointer, |mint| {
+            mint.init_extension::<MetadataPointer>(true).unwrap();
+        });
+        assert!(with_account(Token2022::id(), metadata_pointer, validate_payment_mint).is_ok());
+
+        // Legacy SPL Token mints have no extensions to inspect
+        assert!(with_account(anchor_spl::token::ID, vec![0; 82], validate_payment_mint).is_ok());
+    }
+
+    #[test]
+    fn prize_tiers_must_split_the_whole_pot() {
+        assert!(validate_prize_tiers(&[5000, 3000, 2000]).is_ok());
+        assert!(validate_prize_tiers(&[]).is_err());
+        assert!(validate_prize_tiers(&[5000, 3000]).is_err());
+        assert!(validate_prize_tiers(&[10_000, 0]).is_err());
+        assert!(validate_prize_tiers(&[1000; 11]).is_err());
+    }
+
+    #[test]
+    fn lottery_is_settled_once_everything_is_paid_out() {
+        let mut token_lottery = lottery(10, vec![5000, 5000]);
+        token_lottery.lottery_pot_amount = 95;
+        token_lottery.accrued_fees = 5;
+        token_lottery.record_draw(&randomness(1));
+        assert!(!token_lottery.is_settled());
+
+        token_lottery.mark_tier_claimed(0);
+        token_lottery.mark_tier_claimed(1);
+        assert!(!token_lottery.is_settled());
+
+        token_lottery.accrued_fees = 0;
+        assert!(token_lottery.is_settled());
+
+        let mut cancelled = lottery(1, vec![TOTAL_PRIZE_BPS]);
+        cancelled.cancelled = true;
+        cancelled.lottery_pot_amount = 100;
+        assert!(!cancelled.is_settled());
+        cancelled.lottery_pot_amount = 0;
+        assert!(cancelled.is_settled());
+    }
+
+    #[test]
+    fn uniform_index_stays_in_bounds() {
+        for bound in [1, 2, 3, 7, 255, 256, 257, 1_000, u64::MAX] {
+            for round in 0..500 {
+                assert!(uniform_index(&randomness(round), bound) < bound);
+            }
+        }
+    }
+
+    #[test]
+    fn single_winner_is_uniform_across_ticket_counts() {
+        // Ticket counts above 256 used to leave most tickets unable to win
+        for total_tickets in [2u64, 3, 5, 10, 100, 300, 1_000] {
+            let rounds = total_tickets * 200;
+            let mut counts = vec![0u64; total_tickets as usize];
+            for round in 0..rounds {
+                let winners = draw_winners(&randomness(round), total_tickets, 1);
+                counts[winners[0] as usize] += 1;
+            }
+
+            assert!(counts.iter().all(|&count| count > 0), "{} tickets: some ticket never won", total_tickets);
+
+            // Mean of chi-squared is k - 1 with variance 2(k - 1); allow six standard deviations
+            let degrees = (total_tickets - 1) as f64;
+            let limit = degrees + 6.0 * (2.0 * degrees).sqrt();
+            let statistic = chi_squared(&counts);
+            assert!(statistic < limit, "{} tickets: chi-squared {} exceeds {}", total_tickets, statistic, limit);
+        }
+    }
+
+    #[test]
+    fn every_prize_tier_is_uniform() {
+        let total_tickets = 20u64;
+        let tiers = 3;
+        let mut counts = vec![vec![0u64; total_tickets as usize]; tiers];
+        for round in 0..20_000 {
+            let winners = draw_winners(&randomness(round), total_tickets, tiers);
+            for (tier, &ticket_id) in winners.iter().enumerate() {
+                counts[tier][ticket_id as usize] += 1;
+            }
+        }
+
+        let degrees = (total_tickets - 1) as f64;
+        let limit = degrees + 6.0 * (2.0 * degrees).sqrt();
+        for tier_counts in counts {
+            assert!(chi_squared(&tier_counts) < limit);
+        }
+    }
+
+    #[test]
+    fn winners_are_distinct_and_in_range() {
+        for total_tickets in 1..=12u64 {
+            for round in 0..200 {
+                let count = (MAX_PRIZE_TIERS as u64).min(total_tickets) as usize;
+                let mut winners = draw_winners(&randomness(round), total_tickets, count);
+                assert_eq!(winners.len(), count);
+                assert!(winners.iter().all(|&ticket_id| ticket_id < total_tickets));
+
+                winners.sort_unstable();
+                winners.dedup();
+                assert_eq!(winners.len(), count);
+            }
+        }
+    }
+}
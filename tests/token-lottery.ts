@@ -3,7 +3,20 @@ import * as sb from "@switchboard-xyz/on-demand";
 import { Program } from "@coral-xyz/anchor";
 import { TokenLottery } from "../target/types/token_lottery";
 import { TOKEN_PROGRAM_ID } from "@coral-xyz/anchor/dist/cjs/utils/token";
-import { getAssociatedTokenAddressSync } from "@solana/spl-token";
+import {
+  TOKEN_2022_PROGRAM_ID,
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getMint,
+  getOrCreateAssociatedTokenAccount,
+  getTokenGroupMemberState,
+  getTokenGroupState,
+  getTokenMetadata,
+  mintTo,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import { createHash } from "crypto";
 
 describe("token-lottery", () => {
   // Configure the client to use the local cluster.
@@ -15,8 +28,19 @@ describe("token-lottery", () => {
   const program = anchor.workspace.TokenLottery as Program<TokenLottery>;
   let switchboardProgram;
   const rngKp = anchor.web3.Keypair.generate();
+  const treasury = anchor.web3.Keypair.generate();
   let lotteryEndSlot: number;
 
+  const lotteryId = new anchor.BN(Date.now());
+  const tokenLottery = anchor.web3.PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("token_lottery"),
+      wallet.publicKey.toBuffer(),
+      lotteryId.toArrayLike(Buffer, "le", 8),
+    ],
+    program.programId
+  )[0];
+
   // Helper function to wait for a specific slot
   async function waitForSlot(targetSlot: number) {
     let currentSlot = await connection.getSlot();
@@ -43,8 +67,9 @@ describe("token-lottery", () => {
 
   async function buyTicket() {
     const buyTicketIx = await program.methods
-      .buyTicket()
+      .buyTicket(null)
       .accounts({
+        tokenLottery,
         tokenProgram: TOKEN_PROGRAM_ID,
       })
       .instruction();
@@ -74,6 +99,74 @@ describe("token-lottery", () => {
     console.log("buy ticket ", sig);
   }
 
+  const BUBBLEGUM_PROGRAM_ID = new anchor.web3.PublicKey(
+    "BGUMAp9Gq7iTEuizy4pqaxsTyUCBK68MDfK752saRPUY"
+  );
+  const SPL_ACCOUNT_COMPRESSION_PROGRAM_ID = new anchor.web3.PublicKey(
+    "cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK"
+  );
+  const MPL_CORE_PROGRAM_ID = new anchor.web3.PublicKey(
+    "CoREENxT6tW1HoK8ypY1SxRMZTcVPm7R94rH4PZNhX7d"
+  );
+
+  // Size of a concurrent merkle tree account without a canopy: a 56 byte header,
+  // the sequence number, active index and buffer size, the changelog buffer and the rightmost proof
+  function merkleTreeSize(maxDepth: number, maxBufferSize: number) {
+    const changeLogSize = 32 + 32 * maxDepth + 8;
+    const pathSize = 32 * maxDepth + 32 + 8;
+    return 56 + 24 + maxBufferSize * changeLogSize + pathSize;
+  }
+
+  // Lottery addresses are scoped to the wallet that creates them
+  function lotteryAddress(id: anchor.BN, creator = wallet.publicKey) {
+    return anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("token_lottery"), creator.toBuffer(), id.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    )[0];
+  }
+
+  // Creates a lottery with its collection NFT and returns the lottery address
+  async function createLottery(id: anchor.BN, params, accounts = {}) {
+    const address = lotteryAddress(id);
+    const collectionMint = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("collection_mint"), address.toBuffer()],
+      program.programId
+    )[0];
+    const [metadata] = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("metadata"),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        collectionMint.toBuffer(),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    );
+    const [masterEdition] = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("metadata"),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        collectionMint.toBuffer(),
+        Buffer.from("edition"),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    );
+
+    await program.methods
+      .initializeConfig(id, params)
+      .accounts(accounts)
+      .rpc();
+    await program.methods
+      .initializeLottery()
+      .accounts({
+        tokenLottery: address,
+        masterEdition,
+        metadata,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    return address;
+  }
+
   it("Is initialized!", async () => {
     const slot = await connection.getSlot();
     console.log("Current slot", slot);
@@ -83,7 +176,7 @@ describe("token-lottery", () => {
     console.log("Lottery end slot", lotteryEndSlot);
 
     const mint = anchor.web3.PublicKey.findProgramAddressSync(
-      [Buffer.from("collection_mint")],
+      [Buffer.from("collection_mint"), tokenLottery.toBuffer()],
       program.programId
     )[0];
 
@@ -107,16 +200,32 @@ describe("token-lottery", () => {
     )[0];
 
     const initConfigIx = await program.methods
-      .initializeConfig(
-        new anchor.BN(0),
-        new anchor.BN(lotteryEndSlot),
-        new anchor.BN(10000)
-      )
+      .initializeConfig(lotteryId, {
+        start: new anchor.BN(0),
+        end: new anchor.BN(lotteryEndSlot),
+        price: new anchor.BN(10000),
+        feeBps: 500,
+        treasury: treasury.publicKey,
+        prizeTiers: [5000, 3000, 2000],
+        cancelTimeout: new anchor.BN(1000),
+        winningTicketAction: { burn: {} },
+        maxTickets: null,
+        maxTicketsPerWallet: null,
+        randomnessProvider: { switchboard: { seedSlotWindow: new anchor.BN(10) } },
+        secretCommitment: null,
+        permissionlessDraw: false,
+        crankReward: new anchor.BN(0),
+        recommitTimeout: new anchor.BN(1000),
+        scheduleMode: { slot: {} },
+        metadata: null,
+        ticketMode: { tokenMetadata: {} },
+      })
       .instruction();
 
     const initLotteryIx = await program.methods
       .initializeLottery()
       .accounts({
+        tokenLottery,
         masterEdition: masterEdition,
         metadata: metadata,
         tokenProgram: TOKEN_PROGRAM_ID,
@@ -145,6 +254,18 @@ describe("token-lottery", () => {
     await buyTicket();
     await buyTicket();
     await buyTicket();
+
+    const ticketRecord = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("ticket"),
+        tokenLottery.toBuffer(),
+        new anchor.BN(4).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+    const record = await program.account.ticketRecord.fetch(ticketRecord);
+    assert.equal(record.ticketIndex.toNumber(), 4);
+    assert.isTrue(record.lottery.equals(tokenLottery));
   });
 
   it("Is committing and revealing a winner", async () => {
@@ -201,6 +322,7 @@ describe("token-lottery", () => {
     const commitIx = await program.methods
       .commitWinner()
       .accounts({
+        tokenLottery,
         randomnessAccountData: randomness.pubkey,
       })
       .instruction();
@@ -222,10 +344,26 @@ describe("token-lottery", () => {
     });
     console.log("Transaction Signature for commit: ", commitSignature);
 
+    // A Switchboard draw cannot be rerolled; a stuck one is cancelled instead
+    try {
+      await program.methods
+        .recommitWinner()
+        .accounts({
+          tokenLottery,
+          previousRandomnessAccount: randomness.pubkey,
+          randomnessAccountData: randomness.pubkey,
+        })
+        .rpc();
+      assert.fail("Recommitted a Switchboard draw");
+    } catch (err) {
+      assert.include(err.toString(), "RecommitNotAllowed");
+    }
+
     const sbRevealIx = await randomness.revealIx();
     const revealIx = await program.methods
-      .revealWinner()
+      .revealWinner(null)
       .accounts({
+        tokenLottery,
         randomnessAccountData: randomness.pubkey,
       })
       .instruction();
@@ -249,14 +387,10 @@ describe("token-lottery", () => {
   });
 
   it("Is claiming a prize", async () => {
-    const tokenLotteryAddress = anchor.web3.PublicKey.findProgramAddressSync(
-      [Buffer.from("token_lottery")],
-      program.programId
-    )[0];
     const lotteryConfig = await program.account.tokenLottery.fetch(
-      tokenLotteryAddress
+      tokenLottery
     );
-    console.log("Lottery winner", lotteryConfig.winner);
+    console.log("Lottery winners", lotteryConfig.winningTicketIds);
     console.log("Lottery config", lotteryConfig);
 
     const tokenAccounts = await connection.getParsedTokenAccountsByOwner(
@@ -268,38 +402,1301 @@ describe("token-lottery", () => {
       console.log("Token account address", account.pubkey.toBase58());
     });
 
-    const winningMint = anchor.web3.PublicKey.findProgramAddressSync(
-      [new anchor.BN(lotteryConfig.winner).toArrayLike(Buffer, "le", 8)],
+    // Every ticket belongs to the wallet, so it can claim each prize tier
+    for (const winningTicketId of lotteryConfig.winningTicketIds) {
+      const winningMint = anchor.web3.PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("ticket_mint"),
+          tokenLottery.toBuffer(),
+          winningTicketId.toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      )[0];
+      console.log("Winning mint", winningMint.toBase58());
+
+      const winningTokenAddress = getAssociatedTokenAddressSync(
+        winningMint,
+        wallet.publicKey
+      );
+      console.log("Winning token address", winningTokenAddress.toBase58());
+
+      await program.methods
+        .markWinner(winningTicketId)
+        .accounts({ tokenLottery })
+        .rpc();
+
+      const [winningMetadata] = anchor.web3.PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("metadata"),
+          TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+          winningMint.toBuffer(),
+        ],
+        TOKEN_METADATA_PROGRAM_ID
+      );
+      const metadataInfo = await connection.getAccountInfo(winningMetadata);
+      assert.include(metadataInfo.data.toString(), " WINNER");
+
+      const claimIx = await program.methods
+        .claimPrize(winningTicketId)
+        .accounts({
+          tokenLottery,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .instruction();
+
+      const blockhashContext = await connection.getLatestBlockhash();
+
+      const claimTx = new anchor.web3.Transaction({
+        blockhash: blockhashContext.blockhash,
+        lastValidBlockHeight: blockhashContext.lastValidBlockHeight,
+        feePayer: wallet.payer.publicKey,
+      }).add(claimIx);
+
+      const claimSig = await anchor.web3.sendAndConfirmTransaction(
+        connection,
+        claimTx,
+        [wallet.payer]
+      );
+      console.log(claimSig);
+    }
+
+    const claimedConfig = await program.account.tokenLottery.fetch(
+      tokenLottery
+    );
+    assert.equal(claimedConfig.claimedTiers, 0b111);
+    assert.isTrue(claimedConfig.prizeClaimed);
+
+    // Winning tickets are burned at claim time and every tier is paid once
+    try {
+      await program.methods
+        .claimPrize(claimedConfig.winningTicketIds[0])
+        .accounts({
+          tokenLottery,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      assert.fail("Claimed the same prize twice");
+    } catch (err) {
+      assert.include(err.toString(), "PrizeAlreadyClaimed");
+    }
+  });
+
+  it("Is withdrawing fees to the treasury", async () => {
+    // Keep the treasury rent exempt so it can receive small fee amounts
+    await anchor.web3.sendAndConfirmTransaction(
+      connection,
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.transfer({
+          fromPubkey: wallet.publicKey,
+          toPubkey: treasury.publicKey,
+          lamports: anchor.web3.LAMPORTS_PER_SOL / 100,
+        })
+      ),
+      [wallet.payer]
+    );
+    const before = await connection.getBalance(treasury.publicKey);
+
+    await program.methods
+      .withdrawFees()
+      .accounts({
+        tokenLottery,
+        treasury: treasury.publicKey,
+      })
+      .rpc();
+
+    const after = await connection.getBalance(treasury.publicKey);
+    // 5 tickets * 10000 lamports * 5%
+    assert.equal(after - before, 2500);
+  });
+
+  it("Is closing losing tickets and the settled lottery", async () => {
+    const lotteryConfig = await program.account.tokenLottery.fetch(
+      tokenLottery
+    );
+    const winningIds = lotteryConfig.winningTicketIds.map((id) => id.toNumber());
+
+    // The lottery cannot be closed while ticket records are still open
+    try {
+      await program.methods.closeLottery().accounts({ tokenLottery }).rpc();
+      assert.fail("Closed the lottery with open tickets");
+    } catch (err) {
+      assert.include(err.toString(), "TicketsStillOpen");
+    }
+
+    for (let ticketId = 0; ticketId < 5; ticketId++) {
+      if (winningIds.includes(ticketId)) {
+        continue;
+      }
+      await program.methods
+        .closeTicket(new anchor.BN(ticketId))
+        .accounts({
+          tokenLottery,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+    }
+
+    // Burning the collection NFT reclaims its metadata, edition and token account
+    const collectionMint = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("collection_mint"), tokenLottery.toBuffer()],
       program.programId
     )[0];
-    console.log("Winning mint", winningMint.toBase58());
+    const collectionMetadata = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("metadata"),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        collectionMint.toBuffer(),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    )[0];
+    const collectionMasterEdition = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("metadata"),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        collectionMint.toBuffer(),
+        Buffer.from("edition"),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    )[0];
 
-    const winningTokenAddress = getAssociatedTokenAddressSync(
-      winningMint,
+    await program.methods
+      .closeLottery()
+      .accounts({
+        tokenLottery,
+        collectionMetadata,
+        collectionMasterEdition,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+      })
+      .rpc();
+
+    assert.isNull(await connection.getAccountInfo(tokenLottery));
+    assert.isNull(await connection.getAccountInfo(collectionMetadata));
+    assert.isNull(await connection.getAccountInfo(collectionMasterEdition));
+  });
+
+  it("Is buying a ticket priced in an SPL token", async () => {
+    const tokenLotteryId = new anchor.BN(Date.now());
+    const tokenLotteryAddress = lotteryAddress(tokenLotteryId);
+    const vault = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("vault"), tokenLotteryAddress.toBuffer()],
+      program.programId
+    )[0];
+
+    const paymentMint = await createMint(
+      connection,
+      wallet.payer,
+      wallet.publicKey,
+      null,
+      6
+    );
+    const payerTokenAccount = await getOrCreateAssociatedTokenAccount(
+      connection,
+      wallet.payer,
+      paymentMint,
       wallet.publicKey
     );
-    console.log("Winning token address", winningTokenAddress.toBase58());
+    await mintTo(
+      connection,
+      wallet.payer,
+      paymentMint,
+      payerTokenAccount.address,
+      wallet.payer,
+      1_000_000
+    );
+
+    const slot = await connection.getSlot();
+    await createLottery(
+      tokenLotteryId,
+      {
+        start: new anchor.BN(0),
+        end: new anchor.BN(slot + 1000),
+        price: new anchor.BN(250_000),
+        feeBps: 0,
+        treasury: wallet.publicKey,
+        prizeTiers: null,
+        cancelTimeout: new anchor.BN(0),
+        winningTicketAction: { keep: {} },
+        maxTickets: null,
+        maxTicketsPerWallet: null,
+        randomnessProvider: { switchboard: { seedSlotWindow: new anchor.BN(10) } },
+        secretCommitment: null,
+        permissionlessDraw: false,
+        crankReward: new anchor.BN(0),
+        recommitTimeout: new anchor.BN(1000),
+        scheduleMode: { slot: {} },
+        metadata: null,
+        ticketMode: { tokenMetadata: {} },
+      },
+      {
+        paymentMint,
+        paymentTokenProgram: TOKEN_PROGRAM_ID,
+      }
+    );
 
-    const claimIx = await program.methods
-      .claimPrize()
+    await program.methods
+      .buyTicket(null)
       .accounts({
+        tokenLottery: tokenLotteryAddress,
         tokenProgram: TOKEN_PROGRAM_ID,
+        paymentMint,
+        payerTokenAccount: payerTokenAccount.address,
+        paymentTokenProgram: TOKEN_PROGRAM_ID,
       })
-      .instruction();
+      .preInstructions([
+        anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({
+          units: 300000,
+        }),
+      ])
+      .rpc();
 
-    const blockhashContext = await connection.getLatestBlockhash();
+    const vaultAccount = await getAccount(connection, vault);
+    assert.equal(vaultAccount.amount.toString(), "250000");
+  });
 
-    const claimTx = new anchor.web3.Transaction({
-      blockhash: blockhashContext.blockhash,
-      lastValidBlockHeight: blockhashContext.lastValidBlockHeight,
-      feePayer: wallet.payer.publicKey,
-    }).add(claimIx);
+  it("Is refunding a ticket of a cancelled lottery", async () => {
+    const cancelledLotteryId = new anchor.BN(Date.now());
+    const slot = await connection.getSlot();
+    const cancelledLottery = await createLottery(cancelledLotteryId, {
+      start: new anchor.BN(0),
+      end: new anchor.BN(slot + 1000),
+      price: new anchor.BN(50_000),
+      feeBps: 1000,
+      treasury: wallet.publicKey,
+      prizeTiers: null,
+      cancelTimeout: new anchor.BN(0),
+      winningTicketAction: { keep: {} },
+      maxTickets: null,
+      maxTicketsPerWallet: null,
+      randomnessProvider: { switchboard: { seedSlotWindow: new anchor.BN(10) } },
+      secretCommitment: null,
+      permissionlessDraw: false,
+      crankReward: new anchor.BN(0),
+      recommitTimeout: new anchor.BN(1000),
+      scheduleMode: { slot: {} },
+      metadata: null,
+      ticketMode: { tokenMetadata: {} },
+    });
+
+    await program.methods
+      .buyTicket(null)
+      .accounts({
+        tokenLottery: cancelledLottery,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([
+        anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({
+          units: 300000,
+        }),
+      ])
+      .rpc();
+
+    // Tickets 1..4 are sold in bulk on a single receipt
+    await program.methods
+      .buyTickets(new anchor.BN(3), null)
+      .accounts({ tokenLottery: cancelledLottery })
+      .rpc();
+
+    await program.methods
+      .cancelLottery()
+      .accounts({ tokenLottery: cancelledLottery })
+      .rpc();
+
+    await program.methods
+      .refundTicket(new anchor.BN(0))
+      .accounts({
+        tokenLottery: cancelledLottery,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+    await program.methods
+      .refundReceipt(new anchor.BN(1))
+      .accounts({ tokenLottery: cancelledLottery })
+      .rpc();
+
+    const lotteryConfig = await program.account.tokenLottery.fetch(
+      cancelledLottery
+    );
+    assert.equal(lotteryConfig.totalTickets.toNumber(), 4);
+    assert.isTrue(lotteryConfig.cancelled);
+    assert.equal(lotteryConfig.lotteryPotAmount.toNumber(), 0);
+    assert.equal(lotteryConfig.accruedFees.toNumber(), 0);
+
+    // The ticket and its record are closed, so it cannot be refunded twice
+    const ticketRecord = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("ticket"),
+        cancelledLottery.toBuffer(),
+        new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+    assert.isNull(await connection.getAccountInfo(ticketRecord));
+    try {
+      await program.methods
+        .refundTicket(new anchor.BN(0))
+        .accounts({
+          tokenLottery: cancelledLottery,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+      assert.fail("Refunded the same ticket twice");
+    } catch (err) {
+      assert.include(err.toString(), "AccountNotInitialized");
+    }
+  });
+
+  it("Is enforcing ticket supply and wallet limits", async () => {
+    const cappedLotteryId = new anchor.BN(Date.now());
+    const slot = await connection.getSlot();
+    const cappedLottery = await createLottery(cappedLotteryId, {
+      start: new anchor.BN(0),
+      end: new anchor.BN(slot + 1000),
+      price: new anchor.BN(10_000),
+      feeBps: 0,
+      treasury: wallet.publicKey,
+      prizeTiers: null,
+      cancelTimeout: new anchor.BN(0),
+      winningTicketAction: { keep: {} },
+      maxTickets: new anchor.BN(10),
+      maxTicketsPerWallet: new anchor.BN(2),
+      randomnessProvider: { switchboard: { seedSlotWindow: new anchor.BN(10) } },
+      secretCommitment: null,
+      permissionlessDraw: false,
+      crankReward: new anchor.BN(0),
+      recommitTimeout: new anchor.BN(1000),
+      scheduleMode: { slot: {} },
+      metadata: null,
+      ticketMode: { tokenMetadata: {} },
+    });
+
+    await program.methods
+      .buyTickets(new anchor.BN(2), null)
+      .accounts({ tokenLottery: cappedLottery })
+      .rpc();
+
+    try {
+      await program.methods
+        .buyTickets(new anchor.BN(1), null)
+        .accounts({ tokenLottery: cappedLottery })
+        .rpc();
+      assert.fail("Bought more tickets than the wallet limit");
+    } catch (err) {
+      assert.include(err.toString(), "WalletLimitReached");
+    }
+  });
+
+  it("Is drawing a commit-reveal lottery without an oracle", async () => {
+    const secret = anchor.web3.Keypair.generate().secretKey.slice(0, 32);
+    const secretCommitment = createHash("sha256").update(secret).digest();
+
+    const commitRevealLotteryId = new anchor.BN(Date.now());
+    const slot = await connection.getSlot();
+    const endSlot = slot + 20;
+    const commitRevealLottery = await createLottery(commitRevealLotteryId, {
+      start: new anchor.BN(0),
+      end: new anchor.BN(endSlot),
+      price: new anchor.BN(10_000),
+      feeBps: 0,
+      treasury: wallet.publicKey,
+      prizeTiers: null,
+      cancelTimeout: new anchor.BN(0),
+      winningTicketAction: { keep: {} },
+      maxTickets: null,
+      maxTicketsPerWallet: null,
+      randomnessProvider: { commitReveal: {} },
+      secretCommitment: Array.from(secretCommitment),
+      permissionlessDraw: false,
+      crankReward: new anchor.BN(0),
+      recommitTimeout: new anchor.BN(1000),
+      scheduleMode: { slot: {} },
+      metadata: null,
+      ticketMode: { tokenMetadata: {} },
+    });
+
+    await program.methods
+      .buyTickets(
+        new anchor.BN(5),
+        Array.from(anchor.web3.Keypair.generate().publicKey.toBytes())
+      )
+      .accounts({ tokenLottery: commitRevealLottery })
+      .rpc();
+
+    await waitForSlot(endSlot + 1);
+
+    // Committing fixes the slot whose hash is mixed into the draw
+    await program.methods
+      .commitWinner()
+      .accounts({
+        tokenLottery: commitRevealLottery,
+        randomnessAccountData: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY,
+      })
+      .rpc();
+    const { revealSlot } = await program.account.tokenLottery.fetch(
+      commitRevealLottery
+    );
+    await waitForSlot(revealSlot.toNumber() + 1);
+
+    // A wrong secret is rejected
+    try {
+      await program.methods
+        .revealWinner(Array.from(new Uint8Array(32)))
+        .accounts({
+          tokenLottery: commitRevealLottery,
+          randomnessAccountData: null,
+        })
+        .rpc();
+      assert.fail("Revealed with a wrong secret");
+    } catch (err) {
+      assert.include(err.toString(), "IncorrectSecret");
+    }
+
+    // Drawing without the secret has to wait for the reveal deadline
+    try {
+      await program.methods
+        .revealWinner(null)
+        .accounts({
+          tokenLottery: commitRevealLottery,
+          randomnessAccountData: null,
+        })
+        .rpc();
+      assert.fail("Drew without the secret before the deadline");
+    } catch (err) {
+      assert.include(err.toString(), "RevealDeadlineNotReached");
+    }
+
+    let revealed = null;
+    const listener = program.addEventListener("winnerRevealed", (event) => {
+      revealed = event;
+    });
+
+    await program.methods
+      .revealWinner(Array.from(secret))
+      .accounts({
+        tokenLottery: commitRevealLottery,
+        randomnessAccountData: null,
+      })
+      .rpc();
+
+    const lotteryConfig = await program.account.tokenLottery.fetch(
+      commitRevealLottery
+    );
+    assert.isTrue(lotteryConfig.winnerChosen);
+    assert.isBelow(lotteryConfig.winningTicketIds[0].toNumber(), 5);
+
+    await program.removeEventListener(listener);
+    assert.isNotNull(revealed);
+    assert.isTrue(revealed.lottery.equals(commitRevealLottery));
+    assert.equal(
+      revealed.winningTicketIds[0].toNumber(),
+      lotteryConfig.winningTicketIds[0].toNumber()
+    );
+
+    // The receipt holds the winning ticket, so it closes once the prize is paid
+    const firstTicket = new anchor.BN(0);
+    const receipt = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("receipt"),
+        commitRevealLottery.toBuffer(),
+        firstTicket.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+    try {
+      await program.methods
+        .closeReceipt(firstTicket)
+        .accounts({ tokenLottery: commitRevealLottery })
+        .rpc();
+      assert.fail("Closed a receipt with an unclaimed prize");
+    } catch (err) {
+      assert.include(err.toString(), "UnclaimedPrize");
+    }
 
-    const claimSig = await anchor.web3.sendAndConfirmTransaction(
+    await program.methods
+      .claimReceiptPrize(firstTicket, lotteryConfig.winningTicketIds[0])
+      .accounts({ tokenLottery: commitRevealLottery })
+      .rpc();
+    assert.isNull(await connection.getAccountInfo(receipt));
+  });
+
+  it("Is letting anyone crank a permissionless draw for a reward", async () => {
+    const secret = anchor.web3.Keypair.generate().secretKey.slice(0, 32);
+    const secretCommitment = createHash("sha256").update(secret).digest();
+
+    const crankedLotteryId = new anchor.BN(Date.now());
+    const slot = await connection.getSlot();
+    const endSlot = slot + 20;
+    const crankedLottery = await createLottery(crankedLotteryId, {
+      start: new anchor.BN(0),
+      end: new anchor.BN(endSlot),
+      price: new anchor.BN(10_000),
+      feeBps: 1000,
+      treasury: wallet.publicKey,
+      prizeTiers: null,
+      cancelTimeout: new anchor.BN(0),
+      winningTicketAction: { keep: {} },
+      maxTickets: null,
+      maxTicketsPerWallet: null,
+      randomnessProvider: { commitReveal: {} },
+      secretCommitment: Array.from(secretCommitment),
+      permissionlessDraw: true,
+      crankReward: new anchor.BN(1_500),
+      recommitTimeout: new anchor.BN(1000),
+      scheduleMode: { slot: {} },
+      metadata: null,
+      ticketMode: { tokenMetadata: {} },
+    });
+
+    await program.methods
+      .buyTickets(new anchor.BN(2), null)
+      .accounts({ tokenLottery: crankedLottery })
+      .rpc();
+
+    // Keep the cranker rent exempt so it can receive the reward
+    const cranker = anchor.web3.Keypair.generate();
+    await anchor.web3.sendAndConfirmTransaction(
       connection,
-      claimTx,
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.transfer({
+          fromPubkey: wallet.publicKey,
+          toPubkey: cranker.publicKey,
+          lamports: anchor.web3.LAMPORTS_PER_SOL / 100,
+        })
+      ),
       [wallet.payer]
     );
-    console.log(claimSig);
+
+    await waitForSlot(endSlot + 1);
+
+    await program.methods
+      .commitWinner()
+      .accounts({
+        payer: cranker.publicKey,
+        tokenLottery: crankedLottery,
+        randomnessAccountData: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY,
+      })
+      .signers([cranker])
+      .rpc();
+    const { revealSlot } = await program.account.tokenLottery.fetch(
+      crankedLottery
+    );
+    await waitForSlot(revealSlot.toNumber() + 1);
+
+    await program.methods
+      .revealWinner(Array.from(secret))
+      .accounts({
+        payer: cranker.publicKey,
+        tokenLottery: crankedLottery,
+        randomnessAccountData: null,
+      })
+      .signers([cranker])
+      .rpc();
+
+    const lotteryConfig = await program.account.tokenLottery.fetch(
+      crankedLottery
+    );
+    assert.isTrue(lotteryConfig.winnerChosen);
+    // 2 tickets * 10000 lamports * 10%, less the reward
+    assert.equal(lotteryConfig.accruedFees.toNumber(), 500);
+  });
+
+  it("Is letting the authority update, pause and hand over a lottery", async () => {
+    const adminLotteryId = new anchor.BN(Date.now());
+    const slot = await connection.getSlot();
+    const adminLottery = await createLottery(adminLotteryId, {
+      start: new anchor.BN(0),
+      end: new anchor.BN(slot + 1000),
+      price: new anchor.BN(10_000),
+      feeBps: 0,
+      treasury: wallet.publicKey,
+      prizeTiers: null,
+      cancelTimeout: new anchor.BN(0),
+      winningTicketAction: { keep: {} },
+      maxTickets: null,
+      maxTicketsPerWallet: null,
+      randomnessProvider: { switchboard: { seedSlotWindow: new anchor.BN(10) } },
+      secretCommitment: null,
+      permissionlessDraw: false,
+      crankReward: new anchor.BN(0),
+      recommitTimeout: new anchor.BN(1000),
+      scheduleMode: { slot: {} },
+      metadata: null,
+      ticketMode: { tokenMetadata: {} },
+    });
+
+    const noChanges = {
+      start: null,
+      end: null,
+      price: null,
+      feeBps: null,
+      prizeTiers: null,
+      cancelTimeout: null,
+      winningTicketAction: null,
+      treasury: null,
+      crankReward: null,
+    };
+
+    await program.methods
+      .updateConfig({ ...noChanges, price: new anchor.BN(20_000) })
+      .accounts({ tokenLottery: adminLottery })
+      .rpc();
+
+    await program.methods
+      .setPaused(true)
+      .accounts({ tokenLottery: adminLottery })
+      .rpc();
+    try {
+      await program.methods
+        .buyTickets(new anchor.BN(1), null)
+        .accounts({ tokenLottery: adminLottery })
+        .rpc();
+      assert.fail("Bought a ticket while paused");
+    } catch (err) {
+      assert.include(err.toString(), "LotteryPaused");
+    }
+
+    await program.methods
+      .setPaused(false)
+      .accounts({ tokenLottery: adminLottery })
+      .rpc();
+    await program.methods
+      .buyTickets(new anchor.BN(1), null)
+      .accounts({ tokenLottery: adminLottery })
+      .rpc();
+
+    // Sale terms are locked once a ticket is sold
+    try {
+      await program.methods
+        .updateConfig({ ...noChanges, price: new anchor.BN(1) })
+        .accounts({ tokenLottery: adminLottery })
+        .rpc();
+      assert.fail("Changed the price after tickets were sold");
+    } catch (err) {
+      assert.include(err.toString(), "ConfigLocked");
+    }
+
+    const newAuthority = anchor.web3.Keypair.generate();
+    await program.methods
+      .proposeAuthority(newAuthority.publicKey)
+      .accounts({ tokenLottery: adminLottery })
+      .rpc();
+    await program.methods
+      .acceptAuthority()
+      .accounts({
+        payer: newAuthority.publicKey,
+        tokenLottery: adminLottery,
+      })
+      .signers([newAuthority])
+      .rpc();
+
+    const lotteryConfig = await program.account.tokenLottery.fetch(
+      adminLottery
+    );
+    assert.equal(lotteryConfig.price.toNumber(), 20_000);
+    assert.isTrue(lotteryConfig.authority.equals(newAuthority.publicKey));
+    assert.isNull(lotteryConfig.pendingAuthority);
+    // The handover does not move the lottery: its address stays tied to the creator
+    assert.isTrue(lotteryConfig.creator.equals(wallet.publicKey));
+  });
+
+  it("Is scoping lottery IDs to the wallet that creates them", async () => {
+    const otherCreator = anchor.web3.Keypair.generate();
+    await anchor.web3.sendAndConfirmTransaction(
+      connection,
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.transfer({
+          fromPubkey: wallet.publicKey,
+          toPubkey: otherCreator.publicKey,
+          lamports: anchor.web3.LAMPORTS_PER_SOL / 10,
+        })
+      ),
+      [wallet.payer]
+    );
+
+    // Reusing the ID of the main lottery creates a separate lottery for the other wallet
+    const slot = await connection.getSlot();
+    await program.methods
+      .initializeConfig(lotteryId, {
+        start: new anchor.BN(0),
+        end: new anchor.BN(slot + 1000),
+        price: new anchor.BN(10_000),
+        feeBps: 0,
+        treasury: otherCreator.publicKey,
+        prizeTiers: null,
+        cancelTimeout: new anchor.BN(0),
+        winningTicketAction: { keep: {} },
+        maxTickets: null,
+        maxTicketsPerWallet: null,
+        randomnessProvider: { switchboard: { seedSlotWindow: new anchor.BN(10) } },
+        secretCommitment: null,
+        permissionlessDraw: false,
+        crankReward: new anchor.BN(0),
+        recommitTimeout: new anchor.BN(1000),
+        scheduleMode: { slot: {} },
+        metadata: null,
+        ticketMode: { tokenMetadata: {} },
+      })
+      .accounts({ payer: otherCreator.publicKey })
+      .signers([otherCreator])
+      .rpc();
+
+    const otherLottery = lotteryAddress(lotteryId, otherCreator.publicKey);
+    assert.isFalse(otherLottery.equals(tokenLottery));
+    const otherConfig = await program.account.tokenLottery.fetch(otherLottery);
+    assert.isTrue(otherConfig.creator.equals(otherCreator.publicKey));
+    assert.isTrue(otherConfig.authority.equals(otherCreator.publicKey));
+
+    const mainConfig = await program.account.tokenLottery.fetch(tokenLottery);
+    assert.isTrue(mainConfig.creator.equals(wallet.publicKey));
+  });
+
+  it("Is scheduling a lottery by unix timestamp", async () => {
+    const now = Math.floor(Date.now() / 1000);
+    const params = {
+      start: new anchor.BN(now - 60),
+      end: new anchor.BN(now + 3600),
+      price: new anchor.BN(10_000),
+      feeBps: 0,
+      treasury: wallet.publicKey,
+      prizeTiers: null,
+      cancelTimeout: new anchor.BN(86_400),
+      winningTicketAction: { keep: {} },
+      maxTickets: null,
+      maxTicketsPerWallet: null,
+      randomnessProvider: { switchboard: { seedSlotWindow: new anchor.BN(10) } },
+      secretCommitment: null,
+      permissionlessDraw: false,
+      crankReward: new anchor.BN(0),
+      recommitTimeout: new anchor.BN(1000),
+      scheduleMode: { timestamp: {} },
+      metadata: null,
+      ticketMode: { tokenMetadata: {} },
+    };
+
+    // A window that has already ended is rejected
+    try {
+      await program.methods
+        .initializeConfig(new anchor.BN(Date.now()), {
+          ...params,
+          end: new anchor.BN(now - 1),
+        })
+        .rpc();
+      assert.fail("Created a lottery that already ended");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidSchedule");
+    }
+
+    const timedLottery = await createLottery(
+      new anchor.BN(Date.now() + 1),
+      params
+    );
+    await program.methods
+      .buyTickets(new anchor.BN(1), null)
+      .accounts({ tokenLottery: timedLottery })
+      .rpc();
+
+    const lotteryConfig = await program.account.tokenLottery.fetch(
+      timedLottery
+    );
+    assert.equal(lotteryConfig.totalTickets.toNumber(), 1);
+  });
+
+  it("Is minting compressed tickets into the lottery's merkle tree", async () => {
+    const slot = await connection.getSlot();
+    const compressedLottery = await createLottery(new anchor.BN(Date.now()), {
+      start: new anchor.BN(0),
+      end: new anchor.BN(slot + 1000),
+      price: new anchor.BN(10_000),
+      feeBps: 0,
+      treasury: wallet.publicKey,
+      prizeTiers: null,
+      cancelTimeout: new anchor.BN(0),
+      winningTicketAction: { keep: {} },
+      maxTickets: null,
+      maxTicketsPerWallet: null,
+      randomnessProvider: { switchboard: { seedSlotWindow: new anchor.BN(10) } },
+      secretCommitment: null,
+      permissionlessDraw: false,
+      crankReward: new anchor.BN(0),
+      recommitTimeout: new anchor.BN(1000),
+      scheduleMode: { slot: {} },
+      metadata: null,
+      ticketMode: { compressed: {} },
+    });
+
+    // The tree account is allocated by the client and initialized through the lottery
+    const merkleTree = anchor.web3.Keypair.generate();
+    const space = merkleTreeSize(3, 8);
+    const allocTreeIx = anchor.web3.SystemProgram.createAccount({
+      fromPubkey: wallet.publicKey,
+      newAccountPubkey: merkleTree.publicKey,
+      lamports: await connection.getMinimumBalanceForRentExemption(space),
+      space,
+      programId: SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+    });
+
+    await program.methods
+      .initializeTicketTree(3, 8)
+      .accounts({
+        tokenLottery: compressedLottery,
+        merkleTree: merkleTree.publicKey,
+      })
+      .preInstructions([allocTreeIx])
+      .signers([merkleTree])
+      .rpc();
+
+    // Only compressed tickets can be bought
+    try {
+      await program.methods
+        .buyTicket(null)
+        .accounts({
+          tokenLottery: compressedLottery,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .preInstructions([
+          anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({
+            units: 300000,
+          }),
+        ])
+        .rpc();
+      assert.fail("Bought an NFT ticket in a compressed lottery");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidTicketMode");
+    }
+
+    for (let i = 0; i < 2; i++) {
+      await program.methods
+        .buyCompressedTicket(null)
+        .accounts({
+          tokenLottery: compressedLottery,
+          merkleTree: merkleTree.publicKey,
+        })
+        .preInstructions([
+          anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({
+            units: 400000,
+          }),
+        ])
+        .rpc();
+    }
+
+    const lotteryConfig = await program.account.tokenLottery.fetch(
+      compressedLottery
+    );
+    assert.equal(lotteryConfig.totalTickets.toNumber(), 2);
+    assert.isTrue(lotteryConfig.merkleTree.equals(merkleTree.publicKey));
+
+    // Each ticket is one leaf appended to the tree
+    const treeAccount = await connection.getAccountInfo(merkleTree.publicKey);
+    assert.equal(treeAccount.data.readBigUInt64LE(56), BigInt(2));
+  });
+
+  it("Is minting Token-2022 tickets as members of the collection group", async () => {
+    const groupLotteryId = new anchor.BN(Date.now());
+    const slot = await connection.getSlot();
+    await program.methods
+      .initializeConfig(groupLotteryId, {
+        start: new anchor.BN(0),
+        end: new anchor.BN(slot + 1000),
+        price: new anchor.BN(10_000),
+        feeBps: 0,
+        treasury: wallet.publicKey,
+        prizeTiers: null,
+        cancelTimeout: new anchor.BN(0),
+        winningTicketAction: { burn: {} },
+        maxTickets: new anchor.BN(5),
+        maxTicketsPerWallet: null,
+        randomnessProvider: { switchboard: { seedSlotWindow: new anchor.BN(10) } },
+        secretCommitment: null,
+        permissionlessDraw: false,
+        crankReward: new anchor.BN(0),
+        recommitTimeout: new anchor.BN(1000),
+        scheduleMode: { slot: {} },
+        metadata: null,
+        ticketMode: { token2022: {} },
+      })
+      .rpc();
+
+    const groupLottery = lotteryAddress(groupLotteryId);
+    await program.methods
+      .initializeTokenGroup()
+      .accounts({ tokenLottery: groupLottery })
+      .rpc();
+
+    for (let i = 0; i < 2; i++) {
+      await program.methods
+        .buyToken2022Ticket(null)
+        .accounts({ tokenLottery: groupLottery })
+        .preInstructions([
+          anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({
+            units: 300000,
+          }),
+        ])
+        .rpc();
+    }
+
+    const collectionMint = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("collection_mint"), groupLottery.toBuffer()],
+      program.programId
+    )[0];
+    const ticketMint = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("ticket_mint"),
+        groupLottery.toBuffer(),
+        new anchor.BN(1).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+
+    const group = getTokenGroupState(
+      await getMint(connection, collectionMint, "confirmed", TOKEN_2022_PROGRAM_ID)
+    );
+    assert.equal(Number(group.size), 2);
+    assert.equal(Number(group.maxSize), 5);
+
+    // Each ticket carries its own metadata and points at the collection group
+    const ticket = await getMint(
+      connection,
+      ticketMint,
+      "confirmed",
+      TOKEN_2022_PROGRAM_ID
+    );
+    assert.equal(Number(ticket.supply), 1);
+    assert.isNull(ticket.mintAuthority);
+    const member = getTokenGroupMemberState(ticket);
+    assert.isTrue(member.group.equals(collectionMint));
+    assert.equal(Number(member.memberNumber), 2);
+
+    const metadata = await getTokenMetadata(
+      connection,
+      ticketMint,
+      "confirmed",
+      TOKEN_2022_PROGRAM_ID
+    );
+    assert.equal(metadata.name, "Token Lottery Ticket #1");
+
+    // NFT tickets of this mode are bought only through the Token-2022 path
+    try {
+      await program.methods
+        .buyTicket(null)
+        .accounts({
+          tokenLottery: groupLottery,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .preInstructions([
+          anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({
+            units: 300000,
+          }),
+        ])
+        .rpc();
+      assert.fail("Bought a Metaplex ticket in a Token-2022 lottery");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidTicketMode");
+    }
+  });
+
+  it("Is closing a settled Token-2022 lottery once every ticket is closed", async () => {
+    const secret = anchor.web3.Keypair.generate().secretKey.slice(0, 32);
+    const secretCommitment = createHash("sha256").update(secret).digest();
+
+    const groupLotteryId = new anchor.BN(Date.now());
+    const groupLottery = lotteryAddress(groupLotteryId);
+    const slot = await connection.getSlot();
+    const endSlot = slot + 20;
+    await program.methods
+      .initializeConfig(groupLotteryId, {
+        start: new anchor.BN(0),
+        end: new anchor.BN(endSlot),
+        price: new anchor.BN(10_000),
+        feeBps: 0,
+        treasury: wallet.publicKey,
+        prizeTiers: null,
+        cancelTimeout: new anchor.BN(0),
+        winningTicketAction: { keep: {} },
+        maxTickets: new anchor.BN(5),
+        maxTicketsPerWallet: null,
+        randomnessProvider: { commitReveal: {} },
+        secretCommitment: Array.from(secretCommitment),
+        permissionlessDraw: false,
+        crankReward: new anchor.BN(0),
+        recommitTimeout: new anchor.BN(1000),
+        scheduleMode: { slot: {} },
+        metadata: null,
+        ticketMode: { token2022: {} },
+      })
+      .rpc();
+    await program.methods
+      .initializeTokenGroup()
+      .accounts({ tokenLottery: groupLottery })
+      .rpc();
+
+    for (let i = 0; i < 3; i++) {
+      await program.methods
+        .buyToken2022Ticket(null)
+        .accounts({ tokenLottery: groupLottery })
+        .preInstructions([
+          anchor.web3.ComputeBudgetProgram.setComputeUnitLimit({
+            units: 300000,
+          }),
+        ])
+        .rpc();
+    }
+
+    await waitForSlot(endSlot + 1);
+    await program.methods
+      .commitWinner()
+      .accounts({
+        tokenLottery: groupLottery,
+        randomnessAccountData: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY,
+      })
+      .rpc();
+    const { revealSlot } = await program.account.tokenLottery.fetch(
+      groupLottery
+    );
+    await waitForSlot(revealSlot.toNumber() + 1);
+    await program.methods
+      .revealWinner(Array.from(secret))
+      .accounts({ tokenLottery: groupLottery, randomnessAccountData: null })
+      .rpc();
+
+    const { winningTicketIds } = await program.account.tokenLottery.fetch(
+      groupLottery
+    );
+    const winningId = winningTicketIds[0];
+
+    // The winner has to claim before its ticket can be closed
+    try {
+      await program.methods
+        .closeToken2022Ticket(winningId)
+        .accounts({ tokenLottery: groupLottery })
+        .rpc();
+      assert.fail("Closed an unclaimed winning ticket");
+    } catch (err) {
+      assert.include(err.toString(), "UnclaimedPrize");
+    }
+    await program.methods
+      .claimToken2022Prize(winningId)
+      .accounts({ tokenLottery: groupLottery })
+      .rpc();
+
+    try {
+      await program.methods.closeLottery().accounts({ tokenLottery: groupLottery }).rpc();
+      assert.fail("Closed the lottery with open tickets");
+    } catch (err) {
+      assert.include(err.toString(), "TicketsStillOpen");
+    }
+
+    // Losing tickets and the kept winner are burned, and their mints and records closed
+    for (let ticketId = 0; ticketId < 3; ticketId++) {
+      await program.methods
+        .closeToken2022Ticket(new anchor.BN(ticketId))
+        .accounts({ tokenLottery: groupLottery })
+        .rpc();
+    }
+    const ticketMint = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("ticket_mint"),
+        groupLottery.toBuffer(),
+        new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+    assert.isNull(await connection.getAccountInfo(ticketMint));
+
+    await program.methods.closeLottery().accounts({ tokenLottery: groupLottery }).rpc();
+    assert.isNull(await connection.getAccountInfo(groupLottery));
+  });
+
+  it("Is minting Core asset tickets into the lottery's Core collection", async () => {
+    const coreLotteryId = new anchor.BN(Date.now());
+    const coreLottery = lotteryAddress(coreLotteryId);
+    const slot = await connection.getSlot();
+    await program.methods
+      .initializeConfig(coreLotteryId, {
+        start: new anchor.BN(0),
+        end: new anchor.BN(slot + 1000),
+        price: new anchor.BN(10_000),
+        feeBps: 0,
+        treasury: wallet.publicKey,
+        prizeTiers: null,
+        cancelTimeout: new anchor.BN(0),
+        winningTicketAction: { keep: {} },
+        maxTickets: null,
+        maxTicketsPerWallet: null,
+        randomnessProvider: { switchboard: { seedSlotWindow: new anchor.BN(10) } },
+        secretCommitment: null,
+        permissionlessDraw: false,
+        crankReward: new anchor.BN(0),
+        recommitTimeout: new anchor.BN(1000),
+        scheduleMode: { slot: {} },
+        metadata: null,
+        ticketMode: { core: {} },
+      })
+      .rpc();
+
+    const coreCollection = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("core_collection"), coreLottery.toBuffer()],
+      program.programId
+    )[0];
+    await program.methods
+      .initializeLottery()
+      .accounts({
+        tokenLottery: coreLottery,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        coreCollection,
+        mplCoreProgram: MPL_CORE_PROGRAM_ID,
+        collectionMint: null,
+        collectionTokenAccount: null,
+        metadata: null,
+        masterEdition: null,
+      })
+      .rpc();
+
+    for (let i = 0; i < 2; i++) {
+      await program.methods
+        .buyCoreTicket(null)
+        .accounts({ tokenLottery: coreLottery })
+        .rpc();
+    }
+
+    const lotteryConfig = await program.account.tokenLottery.fetch(
+      coreLottery
+    );
+    assert.equal(lotteryConfig.totalTickets.toNumber(), 2);
+
+    // Core lotteries do not create the SPL collection NFT
+    const collectionMint = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("collection_mint"), coreLottery.toBuffer()],
+      program.programId
+    )[0];
+    assert.isNull(await connection.getAccountInfo(collectionMint));
+
+    // The asset is owned by the buyer and carries its ticket attributes
+    const ticketAsset = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("ticket_mint"),
+        coreLottery.toBuffer(),
+        new anchor.BN(1).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+    const asset = await connection.getAccountInfo(ticketAsset);
+    assert.isTrue(asset.owner.equals(MPL_CORE_PROGRAM_ID));
+    assert.isTrue(
+      new anchor.web3.PublicKey(asset.data.subarray(1, 33)).equals(
+        wallet.publicKey
+      )
+    );
+    assert.include(asset.data.toString("latin1"), "ticket_index");
+    assert.include(asset.data.toString("latin1"), "purchase_slot");
+  });
+
+  it("Is closing a settled Core lottery once every ticket is closed", async () => {
+    const secret = anchor.web3.Keypair.generate().secretKey.slice(0, 32);
+    const secretCommitment = createHash("sha256").update(secret).digest();
+
+    const coreLotteryId = new anchor.BN(Date.now());
+    const coreLottery = lotteryAddress(coreLotteryId);
+    const slot = await connection.getSlot();
+    const endSlot = slot + 20;
+    await program.methods
+      .initializeConfig(coreLotteryId, {
+        start: new anchor.BN(0),
+        end: new anchor.BN(endSlot),
+        price: new anchor.BN(10_000),
+        feeBps: 0,
+        treasury: wallet.publicKey,
+        prizeTiers: null,
+        cancelTimeout: new anchor.BN(0),
+        winningTicketAction: { keep: {} },
+        maxTickets: new anchor.BN(5),
+        maxTicketsPerWallet: null,
+        randomnessProvider: { commitReveal: {} },
+        secretCommitment: Array.from(secretCommitment),
+        permissionlessDraw: false,
+        crankReward: new anchor.BN(0),
+        recommitTimeout: new anchor.BN(1000),
+        scheduleMode: { slot: {} },
+        metadata: null,
+        ticketMode: { core: {} },
+      })
+      .rpc();
+
+    const coreCollection = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("core_collection"), coreLottery.toBuffer()],
+      program.programId
+    )[0];
+    await program.methods
+      .initializeLottery()
+      .accounts({
+        tokenLottery: coreLottery,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        coreCollection,
+        mplCoreProgram: MPL_CORE_PROGRAM_ID,
+        collectionMint: null,
+        collectionTokenAccount: null,
+        metadata: null,
+        masterEdition: null,
+      })
+      .rpc();
+
+    for (let i = 0; i < 2; i++) {
+      await program.methods
+        .buyCoreTicket(null)
+        .accounts({ tokenLottery: coreLottery })
+        .rpc();
+    }
+
+    await waitForSlot(endSlot + 1);
+    await program.methods
+      .commitWinner()
+      .accounts({
+        tokenLottery: coreLottery,
+        randomnessAccountData: anchor.web3.SYSVAR_SLOT_HASHES_PUBKEY,
+      })
+      .rpc();
+    const { revealSlot } = await program.account.tokenLottery.fetch(
+      coreLottery
+    );
+    await waitForSlot(revealSlot.toNumber() + 1);
+    await program.methods
+      .revealWinner(Array.from(secret))
+      .accounts({ tokenLottery: coreLottery, randomnessAccountData: null })
+      .rpc();
+
+    const { winningTicketIds } = await program.account.tokenLottery.fetch(
+      coreLottery
+    );
+    await program.methods
+      .claimCorePrize(winningTicketIds[0])
+      .accounts({ tokenLottery: coreLottery })
+      .rpc();
+
+    try {
+      await program.methods.closeLottery().accounts({ tokenLottery: coreLottery }).rpc();
+      assert.fail("Closed the lottery with open tickets");
+    } catch (err) {
+      assert.include(err.toString(), "TicketsStillOpen");
+    }
+
+    // Every asset is burned and its record closed, the kept winner included
+    for (let ticketId = 0; ticketId < 2; ticketId++) {
+      await program.methods
+        .closeCoreTicket(new anchor.BN(ticketId))
+        .accounts({ tokenLottery: coreLottery })
+        .rpc();
+    }
+    const ticketRecord = anchor.web3.PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("ticket"),
+        coreLottery.toBuffer(),
+        new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+    assert.isNull(await connection.getAccountInfo(ticketRecord));
+
+    await program.methods.closeLottery().accounts({ tokenLottery: coreLottery }).rpc();
+    assert.isNull(await connection.getAccountInfo(coreLottery));
   });
 });